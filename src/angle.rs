@@ -0,0 +1,180 @@
+//! Electrical angle with its sine and cosine.
+//!
+//! [`park`](crate::park_clarke::park) and
+//! [`inverse_park`](crate::park_clarke::inverse_park) take the sine and cosine
+//! of the angle as separate arguments, which are easy to swap or to take from
+//! different angles. [`ElectricalAngle`] keeps the three values together, and
+//! can be passed to [`park_with_angle`](crate::park_clarke::park_with_angle)
+//! and [`inverse_park_with_angle`](crate::park_clarke::inverse_park_with_angle)
+//! instead.
+
+use core::{
+    f32::consts::{FRAC_2_PI, FRAC_PI_2, TAU},
+    ops::{Add, Neg, Sub},
+};
+
+/// An electrical angle along with its sine and cosine.
+///
+/// The sine and cosine are calculated once when the angle is created, using a
+/// polynomial approximation that is accurate to within 1e-6.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ElectricalAngle {
+    angle: f32,
+    sin: f32,
+    cos: f32,
+}
+
+impl ElectricalAngle {
+    /// An angle of zero.
+    pub const ZERO: Self = Self {
+        angle: 0.0,
+        sin: 0.0,
+        cos: 1.0,
+    };
+
+    /// Create a new angle from a value in radians, which is wrapped to the
+    /// range [0, 2π).
+    pub fn new(angle: f32) -> Self {
+        let angle = crate::wrap_angle(angle);
+        let (sin, cos) = sin_cos(angle);
+
+        Self { angle, sin, cos }
+    }
+
+    /// Create a new angle from a mechanical angle in radians, for a motor
+    /// with the given number of pole pairs.
+    pub fn from_mechanical(angle: f32, pole_pairs: u8) -> Self {
+        // Wrap first to keep precision when the mechanical angle is large
+        Self::new(crate::wrap_angle(angle) * pole_pairs as f32)
+    }
+
+    /// Angle in radians, between 0 and 2π.
+    pub fn angle(&self) -> f32 {
+        self.angle
+    }
+
+    /// Sine of the angle.
+    pub fn sin(&self) -> f32 {
+        self.sin
+    }
+
+    /// Cosine of the angle.
+    pub fn cos(&self) -> f32 {
+        self.cos
+    }
+}
+
+impl Default for ElectricalAngle {
+    fn default() -> Self {
+        Self::ZERO
+    }
+}
+
+impl Add for ElectricalAngle {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let mut angle = self.angle + other.angle;
+        if angle >= TAU {
+            angle -= TAU;
+        }
+
+        Self::new(angle)
+    }
+}
+
+impl Sub for ElectricalAngle {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        let mut angle = self.angle - other.angle;
+        if angle < 0.0 {
+            angle += TAU;
+        }
+
+        Self::new(angle)
+    }
+}
+
+impl Neg for ElectricalAngle {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::ZERO - self
+    }
+}
+
+/// Sine and cosine of an angle in radians, between 0 and 2π.
+///
+/// The angle is reduced to within π/4 of the nearest multiple of π/2, where
+/// truncated Taylor series for sine and cosine are accurate to within 1e-6.
+fn sin_cos(angle: f32) -> (f32, f32) {
+    let quadrant = (angle * FRAC_2_PI + 0.5) as u32;
+    let x = angle - quadrant as f32 * FRAC_PI_2;
+    let x2 = x * x;
+
+    let sin = x * (1.0 - x2 / 6.0 * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0)));
+    let cos = 1.0 - x2 / 2.0 * (1.0 - x2 / 12.0 * (1.0 - x2 / 30.0 * (1.0 - x2 / 56.0)));
+
+    match quadrant % 4 {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+
+    #[test]
+    fn sin_cos_accuracy() {
+        let mut worst_error: f32 = 0.0;
+
+        for step in -10_000..=10_000 {
+            let angle = step as f32 * 0.001;
+            let (expected_sin, expected_cos) = libm::sincosf(angle);
+            let angle = ElectricalAngle::new(angle);
+
+            worst_error = worst_error
+                .max((angle.sin() - expected_sin).abs())
+                .max((angle.cos() - expected_cos).abs());
+        }
+
+        assert!(worst_error < 1e-6, "{worst_error}");
+    }
+
+    #[test]
+    fn wraps() {
+        assert!((ElectricalAngle::new(-FRAC_PI_2).angle() - 3.0 * FRAC_PI_2).abs() < 1e-6);
+        assert!((ElectricalAngle::new(5.0 * PI).angle() - PI).abs() < 1e-5);
+    }
+
+    #[test]
+    fn wrapping_arithmetic() {
+        let a = ElectricalAngle::new(5.0);
+        let b = ElectricalAngle::new(2.0);
+
+        let sum = a + b;
+        assert!((sum.angle() - (7.0 - TAU)).abs() < 1e-6);
+        assert!((sum.sin() - libm::sinf(7.0)).abs() < 1e-6);
+
+        let difference = b - a;
+        assert!((difference.angle() - (TAU - 3.0)).abs() < 1e-6);
+        assert!((difference.cos() - libm::cosf(3.0)).abs() < 1e-6);
+
+        let negated = -b;
+        assert!((negated.angle() - (TAU - 2.0)).abs() < 1e-6);
+        assert!((negated.sin() + b.sin()).abs() < 1e-6);
+        assert_eq!(-ElectricalAngle::ZERO, ElectricalAngle::ZERO);
+    }
+
+    #[test]
+    fn from_mechanical() {
+        let angle = ElectricalAngle::from_mechanical(TAU + 1.0, 7);
+        assert!((angle.angle() - (7.0 - TAU)).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,487 @@
+//! Calibration of the offset and gain of the current sensing channels.
+//!
+//! [`CurrentCalibrator`] is run once per PWM period before the current loop
+//! is enabled. It first measures the offset of each channel with no voltage
+//! applied, so every phase is at 50% duty. It can then apply a voltage along
+//! the axis of phase a and then phase b, and use the phase currents summing to
+//! zero to find the mismatch between the gains of the channels. The resulting
+//! [`ChannelCalibration`]s are used by [`MultiShunt`](crate::multi_shunt::MultiShunt)
+//! to correct the raw samples before building the
+//! [`ThreePhaseBalancedReferenceFrame`](crate::park_clarke::ThreePhaseBalancedReferenceFrame)
+//! passed to [`clarke`](crate::park_clarke::clarke).
+
+use crate::{multi_shunt::ChannelCalibration, park_clarke::TwoPhaseReferenceFrame, SQRT_3};
+
+/// Reason for the calibration failing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CalibrationFault {
+    /// The offset of the channel of the given phase is further from the
+    /// nominal offset than the tolerance.
+    Offset(usize),
+    /// The gain of the channel of the given phase is further from the nominal
+    /// gain than the tolerance.
+    Gain(usize),
+    /// Too many samples were rejected as outliers, which indicates excessive
+    /// noise.
+    Outliers,
+    /// The test current was too small to measure the gains, which indicates a
+    /// disconnected motor.
+    NoCurrent,
+}
+
+/// The state of a [`CurrentCalibrator`] after an update.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CalibrationStatus {
+    /// More samples are needed
+    InProgress,
+    /// The calibration of the channel for each phase
+    Complete([ChannelCalibration; 3]),
+    /// The calibration failed
+    Fault(CalibrationFault),
+}
+
+/// Stage of the calibration.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Stage {
+    /// Measuring the offsets with no voltage applied
+    Offset,
+    /// Waiting for the current to settle after applying a voltage along the
+    /// axis of the given phase
+    Settling(usize),
+    /// Measuring the currents with a voltage applied along the axis of the
+    /// given phase
+    Gain(usize),
+    /// The calibration has finished
+    Finished(CalibrationStatus),
+}
+
+/// Mean of the raw samples of each channel, rejecting outliers.
+///
+/// The first three samples are held until their median is known, so that an
+/// outlier among them is rejected rather than taken as the reference.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Accumulator {
+    count: u32,
+    rejected: u32,
+    sum: [f32; 3],
+    initial: [[f32; 3]; 3],
+    held: usize,
+}
+
+impl Accumulator {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            rejected: 0,
+            sum: [0.0; 3],
+            initial: [[0.0; 3]; 3],
+            held: 0,
+        }
+    }
+
+    /// Add a sample, once the initial samples are known.
+    fn add(&mut self, raw: [f32; 3], threshold: f32) {
+        if self.held < self.initial.len() {
+            self.initial[self.held] = raw;
+            self.held += 1;
+
+            if self.held == self.initial.len() {
+                for raw in self.initial {
+                    self.check(raw, threshold);
+                }
+            }
+        } else {
+            self.check(raw, threshold);
+        }
+    }
+
+    /// Add a sample unless any channel is further than the threshold from
+    /// the mean so far, or from the median of the initial samples if none
+    /// have been added.
+    fn check(&mut self, raw: [f32; 3], threshold: f32) {
+        let reference = if self.count > 0 {
+            self.mean()
+        } else {
+            self.median()
+        };
+        if raw
+            .iter()
+            .zip(reference)
+            .any(|(raw, reference)| (raw - reference).abs() > threshold)
+        {
+            self.rejected += 1;
+            return;
+        }
+
+        self.count += 1;
+        for (sum, raw) in self.sum.iter_mut().zip(raw) {
+            *sum += raw;
+        }
+    }
+
+    fn mean(&self) -> [f32; 3] {
+        self.sum.map(|sum| sum / self.count as f32)
+    }
+
+    fn median(&self) -> [f32; 3] {
+        let [first, second, third] = self.initial;
+        let mut median = [0.0; 3];
+        for (channel, median) in median.iter_mut().enumerate() {
+            let (a, b, c) = (first[channel], second[channel], third[channel]);
+            *median = a.min(b).max(a.max(b).min(c));
+        }
+        median
+    }
+}
+
+/// A state machine to calibrate the current sensing channels.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CurrentCalibrator {
+    nominal: ChannelCalibration,
+    samples: u32,
+    offset_tolerance: f32,
+    gain_tolerance: f32,
+    outlier_threshold: f32,
+    test_voltage: f32,
+    settling_samples: u32,
+    minimum_current: f32,
+    stage: Stage,
+    settled: u32,
+    offsets: [f32; 3],
+    currents: [[f32; 3]; 2],
+    accumulator: Accumulator,
+}
+
+impl CurrentCalibrator {
+    /// Create a new calibrator from the nominal calibration of each channel,
+    /// averaging the given number of samples for each measurement.
+    ///
+    /// Only the offsets are measured unless a gain test is configured with
+    /// [`with_gain_test`](Self::with_gain_test).
+    pub const fn new(nominal: ChannelCalibration, samples: u32) -> Self {
+        Self {
+            nominal,
+            samples,
+            offset_tolerance: f32::INFINITY,
+            gain_tolerance: f32::INFINITY,
+            outlier_threshold: f32::INFINITY,
+            test_voltage: 0.0,
+            settling_samples: 0,
+            minimum_current: 0.0,
+            stage: Stage::Offset,
+            settled: 0,
+            offsets: [0.0; 3],
+            currents: [[0.0; 3]; 2],
+            accumulator: Accumulator::new(),
+        }
+    }
+
+    /// Fail if an offset differs from the nominal offset by more than the
+    /// given number of raw units.
+    pub const fn with_offset_tolerance(mut self, tolerance: f32) -> Self {
+        self.offset_tolerance = tolerance;
+        self
+    }
+
+    /// Fail if a gain differs from the nominal gain by more than the given
+    /// fraction of the nominal gain.
+    pub const fn with_gain_tolerance(mut self, tolerance: f32) -> Self {
+        self.gain_tolerance = tolerance;
+        self
+    }
+
+    /// Reject samples where any channel differs from the mean so far by more
+    /// than the given number of raw units. The first three samples are
+    /// compared with their median instead.
+    ///
+    /// The calibration fails if as many samples are rejected as are needed for
+    /// a measurement.
+    pub const fn with_outlier_threshold(mut self, threshold: f32) -> Self {
+        self.outlier_threshold = threshold;
+        self
+    }
+
+    /// Measure the gains by applying the given voltage, normalised as an
+    /// input to a [`Modulation`](crate::pwm::Modulation) method.
+    ///
+    /// The given number of samples are discarded after applying each voltage
+    /// while the current settles. The calibration fails if the test current,
+    /// using the nominal gain, is below the given minimum current.
+    pub const fn with_gain_test(
+        mut self,
+        voltage: f32,
+        settling_samples: u32,
+        minimum_current: f32,
+    ) -> Self {
+        self.test_voltage = voltage;
+        self.settling_samples = settling_samples;
+        self.minimum_current = minimum_current;
+        self
+    }
+
+    /// Voltage to apply during the next PWM period, normalised as an input to
+    /// a [`Modulation`](crate::pwm::Modulation) method.
+    pub fn voltage(&self) -> TwoPhaseReferenceFrame {
+        match self.stage {
+            Stage::Settling(0) | Stage::Gain(0) => TwoPhaseReferenceFrame {
+                alpha: self.test_voltage,
+                beta: 0.0,
+            },
+            Stage::Settling(_) | Stage::Gain(_) => TwoPhaseReferenceFrame {
+                alpha: -self.test_voltage / 2.0,
+                beta: self.test_voltage * SQRT_3 / 2.0,
+            },
+            Stage::Offset | Stage::Finished(_) => TwoPhaseReferenceFrame {
+                alpha: 0.0,
+                beta: 0.0,
+            },
+        }
+    }
+
+    /// Record the raw samples of each channel, taken with the voltage given by
+    /// [`voltage`](Self::voltage) applied.
+    pub fn update(&mut self, raw: [f32; 3]) -> CalibrationStatus {
+        match self.stage {
+            Stage::Offset => {
+                if let Some(offsets) = self.accumulate(raw) {
+                    self.offsets = offsets;
+                    self.stage = match self.check_offsets() {
+                        Some(fault) => Stage::Finished(CalibrationStatus::Fault(fault)),
+                        None if self.test_voltage == 0.0 => Stage::Finished(
+                            CalibrationStatus::Complete(self.calibration([self.nominal.gain; 3])),
+                        ),
+                        None => Stage::Settling(0),
+                    };
+                }
+            }
+            Stage::Settling(phase) => {
+                self.settled += 1;
+                if self.settled >= self.settling_samples {
+                    self.settled = 0;
+                    self.stage = Stage::Gain(phase);
+                }
+            }
+            Stage::Gain(phase) => {
+                if let Some(mean) = self.accumulate(raw) {
+                    for ((current, mean), offset) in
+                        self.currents[phase].iter_mut().zip(mean).zip(self.offsets)
+                    {
+                        *current = mean - offset;
+                    }
+
+                    self.stage = if phase == 0 {
+                        Stage::Settling(1)
+                    } else {
+                        Stage::Finished(self.gains())
+                    };
+                }
+            }
+            Stage::Finished(_) => {}
+        }
+
+        match &self.stage {
+            Stage::Finished(status) => status.clone(),
+            _ => CalibrationStatus::InProgress,
+        }
+    }
+
+    /// Add a sample to the current measurement, returning the mean once
+    /// complete.
+    fn accumulate(&mut self, raw: [f32; 3]) -> Option<[f32; 3]> {
+        self.accumulator.add(raw, self.outlier_threshold);
+
+        if self.accumulator.rejected >= self.samples {
+            self.stage = Stage::Finished(CalibrationStatus::Fault(CalibrationFault::Outliers));
+            None
+        } else if self.accumulator.count >= self.samples {
+            let mean = self.accumulator.mean();
+            self.accumulator = Accumulator::new();
+            Some(mean)
+        } else {
+            None
+        }
+    }
+
+    fn check_offsets(&self) -> Option<CalibrationFault> {
+        self.offsets
+            .iter()
+            .position(|offset| (offset - self.nominal.offset).abs() > self.offset_tolerance)
+            .map(CalibrationFault::Offset)
+    }
+
+    /// Find the gains from the currents measured with the voltage along the
+    /// axis of phase a and phase b.
+    fn gains(&self) -> CalibrationStatus {
+        let [first, second] = self.currents;
+        let minimum = self.minimum_current / self.nominal.gain;
+        let largest = |raw: &[f32; 3]| raw.iter().fold(0.0_f32, |max, raw| max.max(raw.abs()));
+        if largest(&first) < minimum || largest(&second) < minimum {
+            return CalibrationStatus::Fault(CalibrationFault::NoCurrent);
+        }
+
+        // The corrected currents sum to zero for both measurements, so the
+        // gains are perpendicular to both and their scale is unknown. Scale
+        // them so their mean is the nominal gain.
+        let cross = [
+            first[1] * second[2] - first[2] * second[1],
+            first[2] * second[0] - first[0] * second[2],
+            first[0] * second[1] - first[1] * second[0],
+        ];
+        let mean = (cross[0] + cross[1] + cross[2]) / 3.0;
+        let gains = cross.map(|cross| cross / mean * self.nominal.gain);
+
+        let fault = gains.iter().position(|gain| {
+            !gain.is_finite() || (gain / self.nominal.gain - 1.0).abs() > self.gain_tolerance
+        });
+        match fault {
+            Some(phase) => CalibrationStatus::Fault(CalibrationFault::Gain(phase)),
+            None => CalibrationStatus::Complete(self.calibration(gains)),
+        }
+    }
+
+    fn calibration(&self, gains: [f32; 3]) -> [ChannelCalibration; 3] {
+        let mut calibration = [
+            ChannelCalibration::IDEAL,
+            ChannelCalibration::IDEAL,
+            ChannelCalibration::IDEAL,
+        ];
+        for ((calibration, offset), gain) in calibration.iter_mut().zip(self.offsets).zip(gains) {
+            calibration.offset = offset;
+            calibration.gain = gain;
+        }
+        calibration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::park_clarke::inverse_clarke;
+
+    const NOMINAL: ChannelCalibration = ChannelCalibration {
+        offset: 2048.0,
+        gain: 0.01,
+    };
+    const OFFSETS: [f32; 3] = [2040.0, 2061.0, 2050.0];
+    const GAINS: [f32; 3] = [0.0098, 0.0104, 0.0098];
+    /// Current per unit of normalised voltage
+    const ADMITTANCE: f32 = 20.0;
+
+    /// Run the calibrator against channels with the given offsets and gains,
+    /// adding deterministic noise and an outlier every 50 samples.
+    fn run(calibrator: &mut CurrentCalibrator, offsets: [f32; 3]) -> CalibrationStatus {
+        for step in 0..10_000 {
+            let voltage = calibrator.voltage();
+            let currents = inverse_clarke(voltage);
+            let currents = [currents.a, currents.b, currents.c].map(|v| v * ADMITTANCE);
+
+            let noise = if step % 50 == 25 {
+                400.0
+            } else {
+                [-2.0, 1.0, 0.5, 2.0, -1.5][step % 5]
+            };
+            let mut raw = [0.0; 3];
+            for (phase, raw) in raw.iter_mut().enumerate() {
+                *raw = currents[phase] / GAINS[phase] + offsets[phase] + noise;
+            }
+
+            let status = calibrator.update(raw);
+            if status != CalibrationStatus::InProgress {
+                return status;
+            }
+        }
+
+        panic!("calibration did not finish");
+    }
+
+    #[test]
+    fn measures_offsets() {
+        let mut calibrator = CurrentCalibrator::new(NOMINAL, 100).with_outlier_threshold(20.0);
+        let CalibrationStatus::Complete(calibration) = run(&mut calibrator, OFFSETS) else {
+            panic!("calibration failed");
+        };
+
+        for (calibration, offset) in calibration.iter().zip(OFFSETS) {
+            assert!((calibration.offset - offset).abs() < 0.1);
+            assert_eq!(calibration.gain, NOMINAL.gain);
+        }
+
+        // Further updates report the same result
+        assert_eq!(
+            calibrator.update([0.0; 3]),
+            CalibrationStatus::Complete(calibration)
+        );
+    }
+
+    #[test]
+    fn rejects_initial_outlier() {
+        let mut calibrator = CurrentCalibrator::new(NOMINAL, 100).with_outlier_threshold(20.0);
+
+        // A glitch in the first sample must not become the reference
+        calibrator.update(OFFSETS.map(|offset| offset + 400.0));
+        let CalibrationStatus::Complete(calibration) = run(&mut calibrator, OFFSETS) else {
+            panic!("calibration failed");
+        };
+
+        for (calibration, offset) in calibration.iter().zip(OFFSETS) {
+            assert!((calibration.offset - offset).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn measures_gains() {
+        let mut calibrator = CurrentCalibrator::new(NOMINAL, 100)
+            .with_outlier_threshold(20.0)
+            .with_gain_test(0.2, 10, 1.0)
+            .with_gain_tolerance(0.1);
+        let CalibrationStatus::Complete(calibration) = run(&mut calibrator, OFFSETS) else {
+            panic!("calibration failed");
+        };
+
+        for ((calibration, offset), gain) in calibration.iter().zip(OFFSETS).zip(GAINS) {
+            assert!((calibration.offset - offset).abs() < 0.1);
+            assert!(
+                (calibration.gain - gain).abs() < 1e-5,
+                "{}",
+                calibration.gain
+            );
+        }
+    }
+
+    #[test]
+    fn faults() {
+        let mut calibrator = CurrentCalibrator::new(NOMINAL, 100).with_offset_tolerance(10.0);
+        assert_eq!(
+            run(&mut calibrator, OFFSETS),
+            CalibrationStatus::Fault(CalibrationFault::Offset(1))
+        );
+
+        let mut calibrator = CurrentCalibrator::new(NOMINAL, 100).with_outlier_threshold(1.0);
+        assert_eq!(
+            run(&mut calibrator, OFFSETS),
+            CalibrationStatus::Fault(CalibrationFault::Outliers)
+        );
+
+        let mut calibrator = CurrentCalibrator::new(NOMINAL, 100)
+            .with_outlier_threshold(20.0)
+            .with_gain_test(0.2, 10, 10.0);
+        assert_eq!(
+            run(&mut calibrator, OFFSETS),
+            CalibrationStatus::Fault(CalibrationFault::NoCurrent)
+        );
+
+        let mut calibrator = CurrentCalibrator::new(NOMINAL, 100)
+            .with_outlier_threshold(20.0)
+            .with_gain_test(0.2, 10, 1.0)
+            .with_gain_tolerance(0.03);
+        assert_eq!(
+            run(&mut calibrator, OFFSETS),
+            CalibrationStatus::Fault(CalibrationFault::Gain(1))
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Closed-loop current control, combining the reference frame transforms, the
+//! PI controllers and PWM generation into a single step.
+
+use core::marker::PhantomData;
+
+use crate::{
+    angle::ElectricalAngle,
+    park_clarke::{
+        clarke, inverse_park, park, RotatingReferenceFrame, ThreePhaseBalancedReferenceFrame,
+    },
+    pid::PIController,
+    pwm::Modulation,
+    voltage_limit::VoltageLimiter,
+};
+
+/// A field oriented current controller.
+///
+/// Regulates the direct and quadrature axis currents with a PI controller
+/// each, and converts the resulting voltage into PWM compare values using the
+/// modulation method `M`.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CurrentController<M: Modulation> {
+    d: PIController,
+    q: PIController,
+    max: u16,
+    limiter: Option<VoltageLimiter>,
+    modulation: PhantomData<M>,
+}
+
+/// The result of a single step of a [`CurrentController`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CurrentControllerOutput {
+    /// PWM compare values for each phase, between 0 and the maximum compare
+    /// value inclusive
+    pub compare: [u16; 3],
+    /// Measured current in the rotating reference frame
+    pub current: RotatingReferenceFrame,
+    /// Voltage applied in the rotating reference frame, after any limiting,
+    /// in volts
+    pub voltage: RotatingReferenceFrame,
+}
+
+impl<M: Modulation> CurrentController<M> {
+    /// Create a new controller from the direct and quadrature axis PI
+    /// controllers, generating compare values between 0 and `max` inclusive.
+    ///
+    /// The PI controllers take the current error in amps and output a voltage
+    /// in volts.
+    pub const fn new(d: PIController, q: PIController, max: u16) -> Self {
+        Self {
+            d,
+            q,
+            max,
+            limiter: None,
+            modulation: PhantomData,
+        }
+    }
+
+    /// Limit the voltage requested by the PI controllers before modulation.
+    ///
+    /// The limit is scaled by the [`Modulation::VOLTAGE_SCALE`] of `M`, and
+    /// the amount removed from each axis is fed back to the anti-windup of the
+    /// PI controllers.
+    pub const fn with_voltage_limiter(mut self, limiter: VoltageLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Run a single step of the current loop.
+    ///
+    /// Takes the measured phase currents, the cosine and sine of the
+    /// electrical angle, the DC bus voltage and the target current in the
+    /// rotating reference frame.
+    pub fn step(
+        &mut self,
+        currents: ThreePhaseBalancedReferenceFrame,
+        cos_angle: f32,
+        sin_angle: f32,
+        bus_voltage: f32,
+        target: RotatingReferenceFrame,
+        dt: f32,
+    ) -> CurrentControllerOutput {
+        let current = park(cos_angle, sin_angle, clarke(currents));
+
+        let mut voltage = RotatingReferenceFrame {
+            d: self.d.update(target.d, current.d, dt),
+            q: self.q.update(target.q, current.q, dt),
+        };
+
+        if let Some(limiter) = &self.limiter {
+            let limited = limiter.limit_with_sin_cos(
+                voltage,
+                cos_angle,
+                sin_angle,
+                bus_voltage,
+                M::VOLTAGE_SCALE,
+            );
+            self.d.track_saturation(limited.excess.d, dt);
+            self.q.track_saturation(limited.excess.q, dt);
+            voltage = limited.voltage;
+        }
+
+        let compare = M::voltage_as_compare_value(
+            inverse_park(cos_angle, sin_angle, voltage),
+            bus_voltage,
+            self.max,
+        );
+
+        CurrentControllerOutput {
+            compare,
+            current,
+            voltage,
+        }
+    }
+
+    /// Run a single step of the current loop using the sine and cosine cached
+    /// in an [`ElectricalAngle`].
+    pub fn step_with_angle(
+        &mut self,
+        currents: ThreePhaseBalancedReferenceFrame,
+        angle: &ElectricalAngle,
+        bus_voltage: f32,
+        target: RotatingReferenceFrame,
+        dt: f32,
+    ) -> CurrentControllerOutput {
+        self.step(currents, angle.cos(), angle.sin(), bus_voltage, target, dt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        park_clarke::park_with_angle,
+        pid::AntiWindup,
+        pwm::{Sinusoidal, SpaceVector},
+        voltage_limit::{AxisPriority, LimitShape},
+    };
+
+    const DT: f32 = 0.0001;
+    const BUS_VOLTAGE: f32 = 24.0;
+    const RESISTANCE: f32 = 0.5;
+    const INDUCTANCE: f32 = 0.001;
+    const MAX: u16 = 1000;
+
+    /// Run the controller against a stationary RL load for the given number
+    /// of steps, returning the final measured current in the rotating
+    /// reference frame.
+    fn simulate<M: Modulation>(
+        controller: &mut CurrentController<M>,
+        phase_currents: &mut [f32; 3],
+        target: RotatingReferenceFrame,
+        steps: usize,
+    ) -> RotatingReferenceFrame {
+        let angle = ElectricalAngle::new(1.2);
+
+        for _ in 0..steps {
+            let output = controller.step_with_angle(
+                ThreePhaseBalancedReferenceFrame {
+                    a: phase_currents[0],
+                    b: phase_currents[1],
+                },
+                &angle,
+                BUS_VOLTAGE,
+                target,
+                DT,
+            );
+
+            // Average phase voltages, with the common mode removed
+            let voltages = output
+                .compare
+                .map(|compare| (compare as f32 / MAX as f32 - 0.5) * BUS_VOLTAGE);
+            let common = voltages.iter().sum::<f32>() / 3.0;
+            for (current, voltage) in phase_currents.iter_mut().zip(voltages) {
+                *current += (voltage - common - RESISTANCE * *current) * DT / INDUCTANCE;
+            }
+        }
+
+        let current = clarke(ThreePhaseBalancedReferenceFrame {
+            a: phase_currents[0],
+            b: phase_currents[1],
+        });
+        park_with_angle(&angle, current)
+    }
+
+    fn run<M: Modulation>(target: RotatingReferenceFrame) -> RotatingReferenceFrame {
+        let mut controller = CurrentController::<M>::new(
+            PIController::<f32>::new(2.0, 1000.0).with_output_limits(-20.0, 20.0),
+            PIController::<f32>::new(2.0, 1000.0).with_output_limits(-20.0, 20.0),
+            MAX,
+        );
+
+        simulate(&mut controller, &mut [0.0; 3], target, 2000)
+    }
+
+    #[track_caller]
+    fn assert_tracks<M: Modulation>(d: f32, q: f32) {
+        let current = run::<M>(RotatingReferenceFrame { d, q });
+
+        // Compare value quantisation limits the achievable accuracy
+        assert!((current.d - d).abs() < 0.1, "d: {}", current.d);
+        assert!((current.q - q).abs() < 0.1, "q: {}", current.q);
+    }
+
+    #[test]
+    fn tracks_target_space_vector() {
+        assert_tracks::<SpaceVector>(0.0, 5.0);
+        assert_tracks::<SpaceVector>(-3.0, 10.0);
+    }
+
+    #[test]
+    fn tracks_target_sinusoidal() {
+        assert_tracks::<Sinusoidal>(0.0, 5.0);
+        assert_tracks::<Sinusoidal>(-3.0, 10.0);
+    }
+
+    #[test]
+    fn zero_target_centres_outputs() {
+        let mut controller = CurrentController::<SpaceVector>::new(
+            PIController::new(1.0, 1.0),
+            PIController::new(1.0, 1.0),
+            MAX,
+        );
+
+        let output = controller.step(
+            ThreePhaseBalancedReferenceFrame { a: 0.0, b: 0.0 },
+            1.0,
+            0.0,
+            BUS_VOLTAGE,
+            RotatingReferenceFrame { d: 0.0, q: 0.0 },
+            DT,
+        );
+
+        assert_eq!(output.compare, [500; 3]);
+    }
+
+    /// Drive the controller with a voltage limiter towards a current that
+    /// needs more voltage than the bus can produce, returning the steady
+    /// current, then check that it recovers once the target is reachable.
+    fn limited_current<M: Modulation>() -> f32 {
+        let pi = || {
+            PIController::<f32>::new(2.0, 1000.0).with_anti_windup(AntiWindup::BackCalculation {
+                tracking_gain: 500.0,
+            })
+        };
+        let mut controller = CurrentController::<M>::new(pi(), pi(), MAX)
+            .with_voltage_limiter(VoltageLimiter::new(LimitShape::Circle, AxisPriority::Q));
+        let mut phase_currents = [0.0; 3];
+
+        // The load needs 50V for this current, more than the bus can produce
+        let target = RotatingReferenceFrame { d: 0.0, q: 100.0 };
+        let limited = simulate(&mut controller, &mut phase_currents, target, 2000);
+
+        // Recovers as soon as the target is reachable again
+        let target = RotatingReferenceFrame { d: 0.0, q: 5.0 };
+        let current = simulate(&mut controller, &mut phase_currents, target, 100);
+        assert!((current.q - 5.0).abs() < 0.1, "q: {}", current.q);
+
+        limited.q
+    }
+
+    #[test]
+    fn voltage_limiter_prevents_windup() {
+        let current = limited_current::<SpaceVector>();
+        let limit = BUS_VOLTAGE * crate::FRAC_1_SQRT_3 / RESISTANCE;
+        assert!((current - limit).abs() < 0.5, "q: {current}");
+    }
+
+    #[test]
+    fn voltage_limiter_follows_modulation() {
+        // Sinusoidal modulation only reaches half the bus voltage
+        let current = limited_current::<Sinusoidal>();
+        let limit = BUS_VOLTAGE * 0.5 / RESISTANCE;
+        assert!((current - limit).abs() < 0.5, "q: {current}");
+    }
+}
@@ -0,0 +1,199 @@
+//! Dead-time compensation for the PWM stage.
+//!
+//! While both switches of a phase leg are held off during the dead time, the
+//! phase voltage is set by the direction of the current rather than the PWM
+//! signal. The average phase voltage is therefore reduced by `Td / Ts * Vdc`
+//! in the direction of the current, distorting the current waveform around
+//! each zero crossing. [`DeadTimeCompensation`] adds this error back on to the
+//! duty cycles produced by a [`Modulation`](crate::pwm::Modulation) or
+//! [`Modulator`](crate::pwm::Modulator).
+
+use crate::{
+    angle::ElectricalAngle,
+    park_clarke::{inverse_clarke, ThreePhaseReferenceFrame, TwoPhaseReferenceFrame},
+};
+
+/// Compensation of the voltage error caused by inverter dead time.
+///
+/// The polarity of each phase current is taken from a soft sign function,
+/// which is linear for currents within the transition current of zero. This
+/// avoids the compensation switching back and forth on noisy current
+/// measurements around each zero crossing.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeadTimeCompensation {
+    dead_time_fraction: f32,
+    voltage_drop: f32,
+    transition_current: f32,
+}
+
+impl DeadTimeCompensation {
+    /// Create a new compensation for the given dead time and PWM period, in
+    /// the same units of time, and current within which the polarity
+    /// transitions smoothly.
+    pub const fn new(dead_time: f32, period: f32, transition_current: f32) -> Self {
+        Self {
+            dead_time_fraction: dead_time / period,
+            voltage_drop: 0.0,
+            transition_current,
+        }
+    }
+
+    /// Also compensate for the on-state voltage drop of the switches, which
+    /// opposes the current in the same way as the dead time.
+    pub const fn with_voltage_drop(mut self, voltage_drop: f32) -> Self {
+        self.voltage_drop = voltage_drop;
+        self
+    }
+
+    /// Adjust duty cycles, between 0 and 1, given the measured phase currents
+    /// and DC bus voltage.
+    ///
+    /// Positive currents flow out of the inverter into the motor. Returns duty
+    /// cycles between 0 and 1.
+    ///
+    /// A bus voltage that is not positive leaves the duty cycles unchanged,
+    /// rather than dividing by zero.
+    pub fn compensate(
+        &self,
+        duty: [f32; 3],
+        currents: &ThreePhaseReferenceFrame,
+        bus_voltage: f32,
+    ) -> [f32; 3] {
+        if bus_voltage <= 0.0 {
+            return duty;
+        }
+
+        let error = self.dead_time_fraction + self.voltage_drop / bus_voltage;
+        let currents = [currents.a, currents.b, currents.c];
+
+        let mut compensated = duty;
+        for (duty, current) in compensated.iter_mut().zip(currents) {
+            *duty = (*duty + error * self.polarity(current)).clamp(0.0, 1.0);
+        }
+        compensated
+    }
+
+    /// Adjust duty cycles, between 0 and 1, given the angle and magnitude of
+    /// the current vector in the stationary reference frame, and the DC bus
+    /// voltage.
+    ///
+    /// This can be used when the phase currents are too noisy to determine
+    /// their polarity, for example by using the angle of the current
+    /// reference. Returns duty cycles between 0 and 1.
+    pub fn compensate_with_angle(
+        &self,
+        duty: [f32; 3],
+        angle: &ElectricalAngle,
+        magnitude: f32,
+        bus_voltage: f32,
+    ) -> [f32; 3] {
+        let currents = inverse_clarke(TwoPhaseReferenceFrame {
+            alpha: magnitude * angle.cos(),
+            beta: magnitude * angle.sin(),
+        });
+
+        self.compensate(duty, &currents, bus_voltage)
+    }
+
+    /// Soft sign of a phase current.
+    fn polarity(&self, current: f32) -> f32 {
+        if self.transition_current > 0.0 {
+            (current / self.transition_current).clamp(-1.0, 1.0)
+        } else if current > 0.0 {
+            1.0
+        } else if current < 0.0 {
+            -1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn currents(a: f32, b: f32) -> ThreePhaseReferenceFrame {
+        ThreePhaseReferenceFrame { a, b, c: -a - b }
+    }
+
+    #[test]
+    fn compensates_in_direction_of_current() {
+        // 1us dead time with a 50us period
+        let compensation = DeadTimeCompensation::new(1e-6, 50e-6, 0.1);
+        let duty = compensation.compensate([0.5, 0.5, 0.5], &currents(2.0, -1.0), 24.0);
+
+        assert!((duty[0] - 0.52).abs() < 1e-6);
+        assert!((duty[1] - 0.48).abs() < 1e-6);
+        assert!((duty[2] - 0.48).abs() < 1e-6);
+    }
+
+    #[test]
+    fn smooth_transition() {
+        let compensation = DeadTimeCompensation::new(1e-6, 50e-6, 0.1);
+        let duty = compensation.compensate([0.5, 0.5, 0.5], &currents(0.05, 0.0), 24.0);
+
+        assert!((duty[0] - 0.51).abs() < 1e-6);
+        assert!((duty[1] - 0.5).abs() < 1e-6);
+        assert!((duty[2] - 0.49).abs() < 1e-6);
+
+        // Without a transition current the polarity switches at zero
+        let compensation = DeadTimeCompensation::new(1e-6, 50e-6, 0.0);
+        let duty = compensation.compensate([0.5, 0.5, 0.5], &currents(0.05, 0.0), 24.0);
+
+        assert!((duty[0] - 0.52).abs() < 1e-6);
+        assert!((duty[1] - 0.5).abs() < 1e-6);
+        assert!((duty[2] - 0.48).abs() < 1e-6);
+    }
+
+    #[test]
+    fn voltage_drop() {
+        let compensation = DeadTimeCompensation::new(1e-6, 50e-6, 0.1).with_voltage_drop(0.48);
+
+        let duty = compensation.compensate([0.5, 0.5, 0.5], &currents(2.0, -1.0), 24.0);
+        assert!((duty[0] - 0.54).abs() < 1e-6);
+
+        // The voltage drop is a larger fraction of a lower bus voltage
+        let duty = compensation.compensate([0.5, 0.5, 0.5], &currents(2.0, -1.0), 12.0);
+        assert!((duty[0] - 0.56).abs() < 1e-6);
+    }
+
+    #[test]
+    fn zero_bus_voltage() {
+        let compensation = DeadTimeCompensation::new(1e-6, 50e-6, 0.1).with_voltage_drop(0.48);
+        let duty = compensation.compensate([0.4, 0.5, 0.6], &currents(2.0, -1.0), 0.0);
+
+        assert_eq!(duty, [0.4, 0.5, 0.6]);
+    }
+
+    #[test]
+    fn clamps_duty() {
+        let compensation = DeadTimeCompensation::new(1e-6, 50e-6, 0.1);
+        let duty = compensation.compensate([0.99, 0.01, 0.5], &currents(1.0, -1.0), 24.0);
+
+        assert_eq!(duty[0], 1.0);
+        assert_eq!(duty[1], 0.0);
+    }
+
+    #[test]
+    fn angle_matches_currents() {
+        let compensation = DeadTimeCompensation::new(1e-6, 50e-6, 0.1);
+        let duty = [0.4, 0.5, 0.6];
+
+        for step in 0..36 {
+            let angle = ElectricalAngle::new(step as f32 * 0.17);
+            let currents = ThreePhaseReferenceFrame {
+                a: 2.0 * libm::cosf(angle.angle()),
+                b: 2.0 * libm::cosf(angle.angle() - 2.0 * core::f32::consts::FRAC_PI_3),
+                c: 2.0 * libm::cosf(angle.angle() + 2.0 * core::f32::consts::FRAC_PI_3),
+            };
+
+            let expected = compensation.compensate(duty, &currents, 24.0);
+            let actual = compensation.compensate_with_angle(duty, &angle, 2.0, 24.0);
+            for (expected, actual) in expected.iter().zip(actual) {
+                assert!((expected - actual).abs() < 1e-4);
+            }
+        }
+    }
+}
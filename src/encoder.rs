@@ -0,0 +1,377 @@
+//! Quadrature encoder decoding into an electrical angle and speed.
+//!
+//! The encoder is read through a hardware counter, which is sampled on every
+//! update. The counter may wrap around at any value, which need not be a
+//! multiple of the counts per revolution.
+
+use core::f32::consts::TAU;
+
+/// Method used to estimate the speed from the encoder counts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SpeedMethod {
+    /// Count the edges seen in a fixed window of time. Accurate at high
+    /// speeds, but the resolution is one count per window.
+    M {
+        /// Window length in seconds
+        window: f32,
+    },
+    /// Measure the time between consecutive edges. Accurate at low speeds,
+    /// but the resolution is limited by the update period at high speeds.
+    T,
+    /// Count the edges seen in a window of at least the given length, ending
+    /// on an edge, and divide by the time between the first and last edges.
+    /// Accurate over the whole speed range.
+    MT {
+        /// Minimum window length in seconds
+        window: f32,
+    },
+}
+
+/// A quadrature encoder.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Encoder {
+    counts_per_revolution: u32,
+    pole_pairs: u8,
+    method: SpeedMethod,
+    counter_modulus: u32,
+    timeout: f32,
+    offset: f32,
+    last_count: Option<u32>,
+    position: u32,
+    indexed: bool,
+    speed: f32,
+    window_counts: i64,
+    window_time: f32,
+}
+
+impl Encoder {
+    /// Create a new encoder with the given number of counts per mechanical
+    /// revolution, after quadrature decoding, for a motor with the given
+    /// number of pole pairs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `counts_per_revolution` is zero.
+    pub const fn new(counts_per_revolution: u32, pole_pairs: u8, method: SpeedMethod) -> Self {
+        assert!(
+            counts_per_revolution > 0,
+            "counts per revolution must not be zero"
+        );
+
+        Self {
+            counts_per_revolution,
+            pole_pairs,
+            method,
+            counter_modulus: 1 << 16,
+            timeout: 0.1,
+            offset: 0.0,
+            last_count: None,
+            position: 0,
+            indexed: false,
+            speed: 0.0,
+            window_counts: 0,
+            window_time: 0.0,
+        }
+    }
+
+    /// Set the value at which the hardware counter wraps around to zero. This
+    /// defaults to 65536, for a 16-bit counter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `modulus` is zero.
+    pub const fn with_counter_modulus(mut self, modulus: u32) -> Self {
+        assert!(modulus > 0, "counter modulus must not be zero");
+
+        self.counter_modulus = modulus;
+        self
+    }
+
+    /// Set the time without any edges, in seconds, after which the speed is
+    /// taken to be zero. This defaults to 100ms.
+    pub const fn with_timeout(mut self, timeout: f32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Update the encoder with the current value of the hardware counter and
+    /// the time since the last update.
+    pub fn update(&mut self, count: u32, dt: f32) {
+        let count = count % self.counter_modulus;
+        let Some(last_count) = self.last_count.replace(count) else {
+            return;
+        };
+
+        let delta = self.counter_distance(last_count, count);
+        self.position =
+            (self.position as i64 + delta).rem_euclid(self.counts_per_revolution as i64) as u32;
+        self.update_speed(delta, dt);
+    }
+
+    /// Shortest signed distance between two counter values.
+    fn counter_distance(&self, from: u32, to: u32) -> i64 {
+        let modulus = self.counter_modulus as i64;
+        let mut distance = (to as i64 - from as i64).rem_euclid(modulus);
+        if distance > modulus / 2 {
+            distance -= modulus;
+        }
+
+        distance
+    }
+
+    fn update_speed(&mut self, delta: i64, dt: f32) {
+        self.window_counts += delta;
+        self.window_time += dt;
+
+        match self.method {
+            SpeedMethod::M { window } => {
+                if self.window_time >= window {
+                    let radians_per_count = TAU / self.counts_per_revolution as f32;
+                    self.speed = self.window_counts as f32 * radians_per_count / self.window_time;
+                    self.window_counts = 0;
+                    self.window_time = 0.0;
+                }
+            }
+            SpeedMethod::T => self.time_edges(delta, 0.0),
+            SpeedMethod::MT { window } => self.time_edges(delta, window),
+        }
+    }
+
+    /// Estimate the speed from the counts and time between the first and last
+    /// edges of a window of at least the given length.
+    fn time_edges(&mut self, delta: i64, window: f32) {
+        let radians_per_count = TAU / self.counts_per_revolution as f32;
+
+        if delta != 0 && self.window_time >= window {
+            self.speed = self.window_counts as f32 * radians_per_count / self.window_time;
+            self.window_counts = 0;
+            self.window_time = 0.0;
+        } else if self.window_time > self.timeout {
+            self.speed = 0.0;
+            self.window_counts = 0;
+            self.window_time = 0.0;
+        } else if self.window_counts == 0 {
+            // Without an edge the speed can only be bounded by the time since
+            // the last one
+            let bound = radians_per_count / self.window_time;
+            self.speed = self.speed.clamp(-bound, bound);
+        }
+    }
+
+    /// Re-synchronise the position on an index pulse, given the value of the
+    /// hardware counter latched when the pulse occurred. The index marks the
+    /// mechanical zero position.
+    ///
+    /// This must be called after [`Encoder::update`] has been called with a
+    /// counter value at or after the index pulse. Returns the error in the
+    /// position before re-synchronising in counts, where a negative error
+    /// indicates missed counts, or `None` on the first index pulse.
+    pub fn index(&mut self, count: u32) -> Option<i32> {
+        let last_count = self.last_count?;
+        let cpr = self.counts_per_revolution as i64;
+
+        let since_index = self
+            .counter_distance(count % self.counter_modulus, last_count)
+            .rem_euclid(cpr);
+        let mut error = (self.position as i64 - since_index).rem_euclid(cpr);
+        if error > cpr / 2 {
+            error -= cpr;
+        }
+
+        self.position = since_index as u32;
+
+        let correction = self.indexed.then_some(error as i32);
+        self.indexed = true;
+        correction
+    }
+
+    /// Whether an index pulse has been seen.
+    pub fn is_indexed(&self) -> bool {
+        self.indexed
+    }
+
+    /// Set the electrical offset so that the current position corresponds to
+    /// the given electrical angle in radians.
+    ///
+    /// Typically called while a current vector holds the rotor at a known
+    /// electrical angle during alignment.
+    pub fn align(&mut self, electrical_angle: f32) {
+        self.offset = 0.0;
+        self.offset = crate::wrap_angle(electrical_angle - self.angle());
+    }
+
+    /// Electrical offset in radians, as found by [`Encoder::align`].
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    /// Set the electrical offset in radians, such as a value previously found
+    /// by [`Encoder::align`].
+    pub fn set_offset(&mut self, offset: f32) {
+        self.offset = offset;
+    }
+
+    /// Position within the mechanical revolution in counts.
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    /// Mechanical angle in radians, between 0 and 2π.
+    pub fn mechanical_angle(&self) -> f32 {
+        self.position as f32 * TAU / self.counts_per_revolution as f32
+    }
+
+    /// Electrical angle in radians, between 0 and 2π.
+    pub fn angle(&self) -> f32 {
+        // Reduce in integers first to keep full precision at high counts
+        let cpr = self.counts_per_revolution as u64;
+        let electrical = (self.position as u64 * self.pole_pairs as u64) % cpr;
+
+        crate::wrap_angle(electrical as f32 * TAU / cpr as f32 + self.offset)
+    }
+
+    /// Mechanical speed in radians per second.
+    pub fn mechanical_speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Electrical speed in radians per second.
+    pub fn speed(&self) -> f32 {
+        self.speed * self.pole_pairs as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.0001;
+    const CPR: u32 = 4000;
+
+    /// Run an encoder at a constant mechanical speed for one second, starting
+    /// from the given counter value, returning the final counter value.
+    fn run(encoder: &mut Encoder, start: u32, speed: f32) -> u32 {
+        let counts_per_second = speed * CPR as f32 / TAU;
+        let mut count = start;
+
+        for step in 0..=10_000 {
+            let counts = libm::floorf(counts_per_second * step as f32 * DT) as i64;
+            count = (start as i64 + counts).rem_euclid(1 << 16) as u32;
+            encoder.update(count, DT);
+        }
+
+        count
+    }
+
+    #[test]
+    fn counter_wraparound() {
+        let mut encoder = Encoder::new(CPR, 1, SpeedMethod::T);
+
+        encoder.update(65_530, DT);
+        encoder.update(4, DT);
+        assert_eq!(encoder.position(), 10);
+
+        encoder.update(65_520, DT);
+        assert_eq!(encoder.position(), CPR - 10);
+    }
+
+    #[test]
+    fn counter_modulus() {
+        let mut encoder = Encoder::new(CPR, 1, SpeedMethod::T).with_counter_modulus(CPR);
+
+        encoder.update(CPR - 3, DT);
+        encoder.update(2, DT);
+        assert_eq!(encoder.position(), 5);
+    }
+
+    #[test]
+    fn electrical_angle() {
+        let mut encoder = Encoder::new(CPR, 7, SpeedMethod::T);
+
+        encoder.update(0, DT);
+        encoder.update(CPR / 4, DT);
+        assert!((encoder.mechanical_angle() - TAU / 4.0).abs() < 0.0001);
+
+        // 7/4 of an electrical revolution
+        let expected = 3.0 * TAU / 4.0;
+        assert!((encoder.angle() - expected).abs() < 0.0001);
+    }
+
+    #[test]
+    fn alignment() {
+        let mut encoder = Encoder::new(CPR, 4, SpeedMethod::T);
+
+        encoder.update(0, DT);
+        encoder.update(123, DT);
+        encoder.align(0.0);
+        assert!(encoder.angle() < 0.0001 || encoder.angle() > TAU - 0.0001);
+
+        // A quarter of an electrical revolution past a full revolution
+        encoder.update(123 + CPR / 4 + CPR / 16, DT);
+        assert!((encoder.angle() - TAU / 4.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn index_resynchronisation() {
+        let mut encoder = Encoder::new(CPR, 1, SpeedMethod::T);
+
+        encoder.update(1000, DT);
+        encoder.update(1100, DT);
+        assert_eq!(encoder.index(1090), None);
+        assert_eq!(encoder.position(), 10);
+        assert!(encoder.is_indexed());
+
+        // One revolution later, with three counts missed
+        encoder.update(1100 + CPR - 3, DT);
+        assert_eq!(encoder.index(1090 + CPR - 3), Some(-3));
+        assert_eq!(encoder.position(), 10);
+    }
+
+    #[track_caller]
+    fn assert_speed(method: SpeedMethod, speed: f32, tolerance: f32) {
+        let mut encoder = Encoder::new(CPR, 3, method);
+        run(&mut encoder, 65_000, speed);
+
+        let error = (encoder.mechanical_speed() - speed).abs();
+        assert!(
+            error < tolerance * speed.abs(),
+            "{}",
+            encoder.mechanical_speed()
+        );
+        assert!((encoder.speed() - 3.0 * encoder.mechanical_speed()).abs() < 0.001);
+    }
+
+    #[test]
+    fn m_method() {
+        assert_speed(SpeedMethod::M { window: 0.01 }, 100.0, 0.01);
+        assert_speed(SpeedMethod::M { window: 0.01 }, -100.0, 0.01);
+    }
+
+    #[test]
+    fn t_method() {
+        // Edges are only timed to the nearest update
+        assert_speed(SpeedMethod::T, 0.5, 0.05);
+        assert_speed(SpeedMethod::T, -0.5, 0.05);
+    }
+
+    #[test]
+    fn mt_method() {
+        assert_speed(SpeedMethod::MT { window: 0.01 }, 100.0, 0.01);
+        assert_speed(SpeedMethod::MT { window: 0.01 }, 0.5, 0.01);
+        assert_speed(SpeedMethod::MT { window: 0.01 }, -2.0, 0.01);
+    }
+
+    #[test]
+    fn speed_times_out() {
+        let mut encoder = Encoder::new(CPR, 1, SpeedMethod::T).with_timeout(0.05);
+        let count = run(&mut encoder, 0, 10.0);
+
+        for _ in 0..1000 {
+            encoder.update(count, DT);
+        }
+
+        assert_eq!(encoder.speed(), 0.0);
+    }
+}
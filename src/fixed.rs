@@ -0,0 +1,441 @@
+//! Fixed-point number types, for targets without a floating-point unit.
+//!
+//! [`Q15`] and [`Q31`] are fractions between -1 and 1 which implement
+//! [`Number`], so they can be used with the transforms in
+//! [`park_clarke`](crate::park_clarke) and [`space_vector`]. All arithmetic
+//! saturates rather than wrapping on overflow.
+//!
+//! As every value must lie between -1 and 1, voltages and currents need to be
+//! normalised to a suitable full-scale value first. The controllers in
+//! [`pid`](crate::pid) multiply the integral gain by the time step every
+//! update, which is usually below the resolution of a fraction, so
+//! [`PIController`] should be used instead.
+
+use core::ops::{Add, Div, Mul, Neg, Shr, Sub};
+
+use crate::{
+    num::{self, Number},
+    park_clarke::TwoPhaseReferenceFrame,
+    pwm::space_vector,
+};
+
+/// A signed fixed-point fraction between -1 and 1.
+pub trait Fixed: Number + Shr<u8, Output = Self> {
+    /// Multiply by 2 to the power of `shift`, saturating on overflow.
+    fn saturating_shl(self, shift: u8) -> Self;
+
+    /// Map the value from between -1 and 1 to a compare value between 0 and
+    /// the specified maximum value inclusive.
+    fn as_compare_value(self, max: u16) -> u16;
+}
+
+macro_rules! fixed_point {
+    (
+        $(#[$meta:meta])*
+        $name:ident($bits:ty, $wide:ty, $frac:literal),
+        frac_1_sqrt_3: $frac_1_sqrt_3:literal,
+        frac_sqrt_3_2: $frac_sqrt_3_2:literal,
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+        pub struct $name($bits);
+
+        impl $name {
+            /// Create a value from its raw bits.
+            pub const fn from_bits(bits: $bits) -> Self {
+                Self(bits)
+            }
+
+            /// The raw bits of the value.
+            pub const fn to_bits(self) -> $bits {
+                self.0
+            }
+
+            const fn saturate(value: $wide) -> Self {
+                if value > <$bits>::MAX as $wide {
+                    Self(<$bits>::MAX)
+                } else if value < <$bits>::MIN as $wide {
+                    Self(<$bits>::MIN)
+                } else {
+                    Self(value as $bits)
+                }
+            }
+        }
+
+        impl Number for $name {
+            const ZERO: Self = Self(0);
+            const MIN: Self = Self(<$bits>::MIN);
+            const MAX: Self = Self(<$bits>::MAX);
+            const HALF: Self = Self(1 << ($frac - 1));
+            const FRAC_1_SQRT_3: Self = Self($frac_1_sqrt_3);
+            const FRAC_SQRT_3_2: Self = Self($frac_sqrt_3_2);
+
+            fn from_f32(value: f32) -> Self {
+                // Float to integer casts saturate
+                Self(libm::roundf(value * (1u64 << $frac) as f32) as $bits)
+            }
+
+            fn to_f32(self) -> f32 {
+                self.0 as f32 / (1u64 << $frac) as f32
+            }
+        }
+
+        impl Fixed for $name {
+            fn saturating_shl(self, shift: u8) -> Self {
+                // Any non-zero value saturates after shifting by the number of
+                // fractional bits, so larger shifts are not needed
+                Self::saturate((self.0 as $wide) << shift.min($frac))
+            }
+
+            fn as_compare_value(self, max: u16) -> u16 {
+                let offset = self.0 as i64 + (1 << $frac);
+                ((offset * (max as i64 + 1)) >> ($frac + 1)).min(max as i64) as u16
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, other: Self) -> Self {
+                Self(self.0.saturating_add(other.0))
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, other: Self) -> Self {
+                Self(self.0.saturating_sub(other.0))
+            }
+        }
+
+        impl Mul for $name {
+            type Output = Self;
+
+            fn mul(self, other: Self) -> Self {
+                let product = self.0 as $wide * other.0 as $wide;
+                Self::saturate((product + (1 << ($frac - 1))) >> $frac)
+            }
+        }
+
+        impl Div for $name {
+            type Output = Self;
+
+            fn div(self, other: Self) -> Self {
+                if other.0 == 0 {
+                    return match self.0 {
+                        0 => Self(0),
+                        bits if bits > 0 => Self(<$bits>::MAX),
+                        _ => Self(<$bits>::MIN),
+                    };
+                }
+
+                Self::saturate(((self.0 as $wide) << $frac) / other.0 as $wide)
+            }
+        }
+
+        impl Shr<u8> for $name {
+            type Output = Self;
+
+            fn shr(self, shift: u8) -> Self {
+                Self(self.0 >> shift.min($frac))
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+
+            fn neg(self) -> Self {
+                Self(self.0.saturating_neg())
+            }
+        }
+    };
+}
+
+fixed_point! {
+    /// A fixed-point fraction with 15 fractional bits.
+    Q15(i16, i32, 15),
+    frac_1_sqrt_3: 18_919,
+    frac_sqrt_3_2: 28_378,
+}
+
+fixed_point! {
+    /// A fixed-point fraction with 31 fractional bits.
+    Q31(i32, i64, 31),
+    frac_1_sqrt_3: 1_239_850_262,
+    frac_sqrt_3_2: 1_859_775_393,
+}
+
+/// Generate PWM compare values between 0 and the specified maximum value
+/// inclusive, using [`space_vector`].
+pub fn space_vector_compare_value<T: Fixed>(
+    value: TwoPhaseReferenceFrame<T>,
+    max: u16,
+) -> [u16; 3] {
+    space_vector(value).map(|value| value.as_compare_value(max))
+}
+
+/// Fixed-point equivalent of [`pid::PIController`](crate::pid::PIController),
+/// for a controller that is updated at a fixed rate. The integral is limited
+/// to the output limits to prevent windup.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PIController<T> {
+    k_p: T,
+    k_i: T,
+    shift: u8,
+    integral: T,
+    min: T,
+    max: T,
+}
+
+impl<T: Fixed> PIController<T> {
+    /// Create a new controller with the given gains.
+    ///
+    /// Both gains are multiplied by 2 to the power of `shift`, to allow gains
+    /// greater than 1. The integral gain is per update, so for an integral
+    /// gain of `k_i` per second updated every `dt` seconds it should be
+    /// `k_i * dt`.
+    pub fn new(k_p: T, k_i: T, shift: u8) -> Self {
+        Self {
+            k_p,
+            k_i,
+            shift,
+            integral: T::ZERO,
+            min: T::MIN,
+            max: T::MAX,
+        }
+    }
+
+    /// Limit the output of the controller to the given range.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is greater than `max`.
+    pub fn with_output_limits(mut self, min: T, max: T) -> Self {
+        assert!(min <= max, "min must not be greater than max");
+
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    /// Update the controller, returning the new output value.
+    pub fn update(&mut self, setpoint: T, measurement: T) -> T {
+        let error = setpoint - measurement;
+
+        // Sum the terms before applying the shift, so that a saturated
+        // proportional term can still be offset by the integral
+        self.integral = num::clamp(
+            self.integral + self.k_i * error,
+            self.min >> self.shift,
+            self.max >> self.shift,
+        );
+        let output = (self.k_p * error + self.integral).saturating_shl(self.shift);
+
+        num::clamp(output, self.min, self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        park_clarke::{self, ThreePhaseBalancedReferenceFrame},
+        pid,
+        pwm::{Modulation, SpaceVector},
+    };
+
+    /// Error bounds for each format, allowing for a few least significant
+    /// bits of rounding error.
+    const Q15_TOLERANCE: f32 = 4.0 / 32768.0;
+    const Q31_TOLERANCE: f32 = 1e-6;
+
+    /// Evenly spaced values between -0.5 and 0.5.
+    fn values() -> impl DoubleEndedIterator<Item = f32> {
+        (-10..=10).map(|step| step as f32 / 20.0)
+    }
+
+    #[track_caller]
+    fn assert_close<T: Fixed>(fixed: T, expected: f32, tolerance: f32) {
+        let error = (fixed.to_f32() - expected).abs();
+        assert!(error < tolerance, "{} != {expected}", fixed.to_f32());
+    }
+
+    fn clarke_matches<T: Fixed>(tolerance: f32) {
+        for a in values() {
+            for b in values() {
+                let expected = park_clarke::clarke(ThreePhaseBalancedReferenceFrame { a, b });
+                let result = park_clarke::clarke(ThreePhaseBalancedReferenceFrame {
+                    a: T::from_f32(a),
+                    b: T::from_f32(b),
+                });
+                assert_close(result.alpha, expected.alpha, tolerance);
+                assert_close(result.beta, expected.beta, tolerance);
+
+                let expected =
+                    park_clarke::inverse_clarke(TwoPhaseReferenceFrame { alpha: a, beta: b });
+                let result = park_clarke::inverse_clarke(TwoPhaseReferenceFrame {
+                    alpha: T::from_f32(a),
+                    beta: T::from_f32(b),
+                });
+                assert_close(result.a, expected.a, tolerance);
+                assert_close(result.b, expected.b, tolerance);
+                assert_close(result.c, expected.c, tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn clarke_matches_f32() {
+        clarke_matches::<Q15>(Q15_TOLERANCE);
+        clarke_matches::<Q31>(Q31_TOLERANCE);
+    }
+
+    fn park_matches<T: Fixed>(tolerance: f32) {
+        for step in 0..100 {
+            let (sin_angle, cos_angle) = libm::sincosf(step as f32 * 0.0628);
+            let (sin_fixed, cos_fixed) = (T::from_f32(sin_angle), T::from_f32(cos_angle));
+
+            for (alpha, beta) in values().zip(values().rev()) {
+                let input = TwoPhaseReferenceFrame { alpha, beta };
+                let expected = park_clarke::park(cos_angle, sin_angle, input);
+                let result = park_clarke::park(
+                    cos_fixed,
+                    sin_fixed,
+                    TwoPhaseReferenceFrame {
+                        alpha: T::from_f32(alpha),
+                        beta: T::from_f32(beta),
+                    },
+                );
+                assert_close(result.d, expected.d, tolerance);
+                assert_close(result.q, expected.q, tolerance);
+
+                let expected = park_clarke::inverse_park(cos_angle, sin_angle, expected);
+                let result = park_clarke::inverse_park(cos_fixed, sin_fixed, result);
+                assert_close(result.alpha, expected.alpha, 2.0 * tolerance);
+                assert_close(result.beta, expected.beta, 2.0 * tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn park_matches_f32() {
+        park_matches::<Q15>(Q15_TOLERANCE);
+        park_matches::<Q31>(Q31_TOLERANCE);
+    }
+
+    fn space_vector_matches<T: Fixed>(tolerance: f32) {
+        for step in 0..100 {
+            let (sin_angle, cos_angle) = libm::sincosf(step as f32 * 0.0628);
+
+            for magnitude in [0.0, 0.3, 0.9] {
+                let alpha = magnitude * cos_angle;
+                let beta = magnitude * sin_angle;
+                let expected = SpaceVector::modulate(TwoPhaseReferenceFrame { alpha, beta });
+                let result = space_vector(TwoPhaseReferenceFrame {
+                    alpha: T::from_f32(alpha),
+                    beta: T::from_f32(beta),
+                });
+
+                for (result, expected) in result.into_iter().zip(expected) {
+                    assert_close(result, expected, tolerance);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn space_vector_matches_f32() {
+        space_vector_matches::<Q15>(Q15_TOLERANCE);
+        space_vector_matches::<Q31>(Q31_TOLERANCE);
+    }
+
+    #[test]
+    fn compare_values_match_f32() {
+        for step in 0..100 {
+            let (sin_angle, cos_angle) = libm::sincosf(step as f32 * 0.0628);
+            let value = TwoPhaseReferenceFrame {
+                alpha: 0.9 * cos_angle,
+                beta: 0.9 * sin_angle,
+            };
+            let expected = SpaceVector::as_compare_value(value, 1000);
+            let result = space_vector_compare_value(
+                TwoPhaseReferenceFrame {
+                    alpha: Q15::from_f32(value.alpha),
+                    beta: Q15::from_f32(value.beta),
+                },
+                1000,
+            );
+
+            for (result, expected) in result.into_iter().zip(expected) {
+                assert!(result.abs_diff(expected) <= 1, "{result} != {expected}");
+            }
+        }
+
+        assert_eq!(Q15::MAX.as_compare_value(1000), 1000);
+        assert_eq!(Q15::MIN.as_compare_value(1000), 0);
+        assert_eq!(Q31::ZERO.as_compare_value(1000), 500);
+    }
+
+    #[test]
+    fn saturates() {
+        assert_eq!(Q15::from_f32(1.5), Q15::MAX);
+        assert_eq!(Q15::from_f32(-1.5), Q15::MIN);
+        assert_eq!(Q15::from_f32(0.75) + Q15::from_f32(0.75), Q15::MAX);
+        assert_eq!(Q31::from_f32(-0.75) - Q31::from_f32(0.75), Q31::MIN);
+        assert_eq!(-Q15::MIN, Q15::MAX);
+        assert_eq!(Q15::MIN * Q15::MIN, Q15::MAX);
+        assert_eq!(Q15::from_f32(0.25) / Q15::from_f32(0.5), Q15::HALF);
+        assert_eq!(Q15::from_f32(0.5) / Q15::from_f32(0.25), Q15::MAX);
+        assert_eq!(Q31::from_f32(-0.5) / Q31::from_f32(0.25), Q31::MIN);
+        assert_eq!(Q31::from_f32(-0.5) / Q31::ZERO, Q31::MIN);
+        assert_eq!(Q31::ZERO / Q31::ZERO, Q31::ZERO);
+    }
+
+    fn pi_matches<T: Fixed>(tolerance: f32) {
+        const DT: f32 = 0.001;
+
+        // Gains of 1.5 and 40 per second, expressed with a shift of 1
+        let mut fixed = PIController::new(T::from_f32(0.75), T::from_f32(0.02), 1)
+            .with_output_limits(T::from_f32(-0.8), T::from_f32(0.8));
+        let mut float = pid::PIController::<f32>::new(1.5, 40.0)
+            .with_output_limits(-0.8, 0.8)
+            .with_anti_windup(pid::AntiWindup::IntegratorLimit {
+                min: -0.8,
+                max: 0.8,
+            });
+
+        // First order plant with unity gain
+        let mut measurement = 0.0;
+        for step in 0..2000 {
+            let setpoint = if step < 1000 { 0.5 } else { -0.3 };
+
+            let output = fixed.update(T::from_f32(setpoint), T::from_f32(measurement));
+            let expected = float.update(setpoint, measurement, DT);
+            assert_close(output, expected, tolerance);
+
+            measurement += (expected - measurement) * 0.05;
+        }
+
+        assert!((measurement + 0.3).abs() < 0.01);
+    }
+
+    #[test]
+    fn pi_matches_f32() {
+        pi_matches::<Q15>(0.01);
+        pi_matches::<Q31>(Q31_TOLERANCE * 10.0);
+    }
+
+    #[test]
+    fn pi_saturates() {
+        let mut controller = PIController::new(Q15::MAX, Q15::MAX, 4)
+            .with_output_limits(Q15::from_f32(-0.5), Q15::from_f32(0.5));
+
+        for _ in 0..10 {
+            assert_eq!(controller.update(Q15::MAX, Q15::MIN), Q15::from_f32(0.5));
+        }
+        assert_eq!(controller.update(Q15::MIN, Q15::MAX), Q15::from_f32(-0.5));
+    }
+}
@@ -0,0 +1,200 @@
+//! Nonlinear flux observer for sensorless estimation of the rotor angle.
+//!
+//! Implements the observer proposed by Ortega et al. in "Sensorless Control of
+//! Permanent Magnet Synchronous Motors: An Observer Approach". The stator flux
+//! is obtained by integrating the back-EMF, and corrected by a term that drives
+//! the magnitude of the resulting rotor flux estimate towards the known flux
+//! linkage. This removes the integrator drift that would otherwise prevent
+//! operation at low speed.
+
+use crate::{park_clarke::TwoPhaseReferenceFrame, pll::PhaseLockedLoop};
+
+/// A nonlinear flux observer.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FluxObserver {
+    resistance: f32,
+    inductance: f32,
+    flux_linkage: f32,
+    gain: f32,
+    stator_flux: TwoPhaseReferenceFrame,
+    rotor_flux: TwoPhaseReferenceFrame,
+    pll: PhaseLockedLoop,
+}
+
+impl FluxObserver {
+    /// Create a new observer for a motor with the given phase resistance in
+    /// ohms, phase inductance in henries and permanent magnet flux linkage in
+    /// webers.
+    ///
+    /// The observer gain can be chosen with [`FluxObserver::gain`]. The speed
+    /// is estimated by a phase-locked loop with the given bandwidth in radians
+    /// per second.
+    pub const fn new(
+        resistance: f32,
+        inductance: f32,
+        flux_linkage: f32,
+        gain: f32,
+        pll_bandwidth: f32,
+    ) -> Self {
+        Self {
+            resistance,
+            inductance,
+            flux_linkage,
+            gain,
+            stator_flux: TwoPhaseReferenceFrame {
+                alpha: 0.0,
+                beta: 0.0,
+            },
+            rotor_flux: TwoPhaseReferenceFrame {
+                alpha: 0.0,
+                beta: 0.0,
+            },
+            pll: PhaseLockedLoop::from_bandwidth(pll_bandwidth),
+        }
+    }
+
+    /// Calculate the observer gain that makes errors in the magnitude of the
+    /// rotor flux estimate decay at the given rate, in radians per second.
+    ///
+    /// The convergence rate should be well above the highest electrical speed
+    /// while staying below the sample rate.
+    pub const fn gain(flux_linkage: f32, convergence_rate: f32) -> f32 {
+        convergence_rate / (flux_linkage * flux_linkage)
+    }
+
+    /// Update the observer with the applied voltage and measured current in
+    /// the stationary reference frame.
+    pub fn update(
+        &mut self,
+        voltage: &TwoPhaseReferenceFrame,
+        current: &TwoPhaseReferenceFrame,
+        dt: f32,
+    ) {
+        let flux_error = self.flux_linkage * self.flux_linkage
+            - (self.rotor_flux.alpha * self.rotor_flux.alpha
+                + self.rotor_flux.beta * self.rotor_flux.beta);
+        let correction = self.gain / 2.0 * flux_error;
+
+        self.stator_flux.alpha += (voltage.alpha - self.resistance * current.alpha
+            + correction * self.rotor_flux.alpha)
+            * dt;
+        self.stator_flux.beta += (voltage.beta - self.resistance * current.beta
+            + correction * self.rotor_flux.beta)
+            * dt;
+
+        self.rotor_flux = TwoPhaseReferenceFrame {
+            alpha: self.stator_flux.alpha - self.inductance * current.alpha,
+            beta: self.stator_flux.beta - self.inductance * current.beta,
+        };
+
+        let (sin_angle, cos_angle) = self.sin_cos();
+        self.pll.update_sin_cos(sin_angle, cos_angle, dt);
+    }
+
+    /// Estimated electrical angle in radians, between 0 and 2π.
+    pub fn angle(&self) -> f32 {
+        crate::wrap_angle(libm::atan2f(self.rotor_flux.beta, self.rotor_flux.alpha))
+    }
+
+    /// Sine and cosine of the estimated electrical angle, as used by
+    /// [`park`](crate::park_clarke::park) and
+    /// [`inverse_park`](crate::park_clarke::inverse_park).
+    ///
+    /// These are taken directly from the rotor flux estimate, avoiding the
+    /// need to evaluate any trigonometric functions.
+    pub fn sin_cos(&self) -> (f32, f32) {
+        let magnitude = libm::hypotf(self.rotor_flux.alpha, self.rotor_flux.beta);
+        if magnitude > 0.0 {
+            (
+                self.rotor_flux.beta / magnitude,
+                self.rotor_flux.alpha / magnitude,
+            )
+        } else {
+            (0.0, 1.0)
+        }
+    }
+
+    /// Estimated electrical speed in radians per second.
+    pub fn speed(&self) -> f32 {
+        self.pll.speed()
+    }
+
+    /// Estimated rotor flux in webers, in the stationary reference frame.
+    pub fn rotor_flux(&self) -> &TwoPhaseReferenceFrame {
+        &self.rotor_flux
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Motor;
+
+    const DT: f32 = 0.00005;
+    const FLUX_LINKAGE: f32 = 0.01;
+
+    /// Spin a simulated motor at the given electrical speed, returning the
+    /// observer after one second.
+    fn run(speed: f32, resistance_error: f32) -> (Motor, FluxObserver) {
+        let mut motor = Motor::new(0.5, 0.0005, FLUX_LINKAGE, speed);
+        let gain = FluxObserver::gain(FLUX_LINKAGE, 2000.0);
+        let mut observer =
+            FluxObserver::new(0.5 * resistance_error, 0.0005, FLUX_LINKAGE, gain, 300.0);
+
+        for _ in 0..20_000 {
+            let voltage = motor.feedforward(2.0);
+            observer.update(&voltage, &motor.current, DT);
+            motor.step(&voltage, DT);
+        }
+
+        (motor, observer)
+    }
+
+    #[track_caller]
+    fn assert_converges(speed: f32, resistance_error: f32, tolerance: f32) {
+        let (motor, observer) = run(speed, resistance_error);
+
+        let angle_error = crate::wrap_angle_difference(observer.angle() - motor.angle);
+        assert!(angle_error.abs() < tolerance, "angle error: {angle_error}");
+        assert!(
+            (observer.speed() - speed).abs() < 0.02 * speed.abs(),
+            "speed: {}",
+            observer.speed()
+        );
+    }
+
+    #[test]
+    fn converges_high_speed() {
+        assert_converges(1500.0, 1.0, 0.02);
+        assert_converges(-1500.0, 1.0, 0.02);
+    }
+
+    #[test]
+    fn converges_low_speed() {
+        assert_converges(50.0, 1.0, 0.05);
+        assert_converges(-50.0, 1.0, 0.05);
+    }
+
+    #[test]
+    fn tolerates_resistance_error() {
+        assert_converges(500.0, 1.05, 0.1);
+    }
+
+    #[test]
+    fn sin_cos_matches_angle() {
+        let (_, observer) = run(500.0, 1.0);
+
+        let (sin_angle, cos_angle) = observer.sin_cos();
+        let (expected_sin, expected_cos) = libm::sincosf(observer.angle());
+        assert!((sin_angle - expected_sin).abs() < 0.0001);
+        assert!((cos_angle - expected_cos).abs() < 0.0001);
+    }
+
+    #[test]
+    fn rotor_flux_magnitude() {
+        let (_, observer) = run(500.0, 1.0);
+
+        let magnitude = libm::hypotf(observer.rotor_flux().alpha, observer.rotor_flux().beta);
+        assert!((magnitude - FLUX_LINKAGE).abs() < 0.01 * FLUX_LINKAGE);
+    }
+}
@@ -0,0 +1,393 @@
+//! Decoding of three Hall effect sensors into an electrical angle.
+//!
+//! The three sensors are combined into a 3-bit state, with sensor A in the
+//! least significant bit. With sensors spaced 120° apart, the six valid
+//! states each cover 60° of electrical angle, and consecutive states differ
+//! by a single bit.
+
+use crate::park_clarke::TwoPhaseReferenceFrame;
+
+/// Sector of each Hall state, numbered in the order they are visited for a
+/// positive rotation. The states with all sensors equal are invalid.
+const SECTORS: [Option<u8>; 8] = [
+    None,
+    Some(0),
+    Some(2),
+    Some(1),
+    Some(4),
+    Some(5),
+    Some(3),
+    None,
+];
+
+/// Error detected while decoding the Hall sensor state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HallError {
+    /// All sensors have the same level, which indicates a disconnected or
+    /// faulty sensor.
+    InvalidState(u8),
+    /// The state changed to one that is not adjacent to the previous state,
+    /// which indicates a missed transition or noise.
+    SequenceError {
+        /// Previous Hall state
+        previous: u8,
+        /// New Hall state
+        current: u8,
+    },
+}
+
+/// Decode a 3-bit Hall state into a sector between 0 and 5.
+pub fn sector(state: u8) -> Result<u8, HallError> {
+    SECTORS
+        .get(state as usize)
+        .copied()
+        .flatten()
+        .ok_or(HallError::InvalidState(state))
+}
+
+/// Electrical angles at the centre of each sector, for sensors that are
+/// evenly spaced with the first sector centred on the given angle.
+pub const fn evenly_spaced_angles(offset: f32) -> [f32; 6] {
+    let step = core::f32::consts::FRAC_PI_3;
+    [
+        offset,
+        offset + step,
+        offset + 2.0 * step,
+        offset + 3.0 * step,
+        offset + 4.0 * step,
+        offset + 5.0 * step,
+    ]
+}
+
+/// Three Hall effect sensors, providing an electrical angle interpolated
+/// between state transitions.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HallSensor {
+    angles: [f32; 6],
+    timeout: f32,
+    state: Option<u8>,
+    edge_angle: f32,
+    limit: f32,
+    direction: i8,
+    speed: f32,
+    elapsed: f32,
+}
+
+impl HallSensor {
+    /// Create a new decoder using the given electrical angle at the centre of
+    /// each sector, in radians.
+    ///
+    /// The speed is assumed to be zero, and the angle held at the centre of
+    /// the sector, when no transition is seen for `timeout` seconds.
+    pub const fn new(angles: [f32; 6], timeout: f32) -> Self {
+        Self {
+            angles,
+            timeout,
+            state: None,
+            edge_angle: 0.0,
+            limit: 0.0,
+            direction: 0,
+            speed: 0.0,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Update the decoder with the current Hall state and the time since the
+    /// last update, returning the interpolated electrical angle.
+    ///
+    /// On error the speed estimate is reset, and the decoder resynchronises
+    /// on the next valid state.
+    pub fn update(&mut self, state: u8, dt: f32) -> Result<f32, HallError> {
+        let sector = match sector(state) {
+            Ok(sector) => sector,
+            Err(error) => {
+                self.state = None;
+                return Err(error);
+            }
+        };
+        self.elapsed += dt;
+
+        match self.state {
+            Some(previous) if previous == state => {}
+            Some(previous) => {
+                let previous_sector = SECTORS[previous as usize].unwrap_or_default();
+                let step = match (sector + 6 - previous_sector) % 6 {
+                    1 => 1,
+                    5 => -1,
+                    _ => {
+                        self.state = None;
+                        return Err(HallError::SequenceError {
+                            previous,
+                            current: state,
+                        });
+                    }
+                };
+
+                let edge_angle = self.boundary(previous_sector, step);
+                let travelled = crate::wrap_angle_difference(edge_angle - self.edge_angle);
+
+                // The distance between edges is only known after two
+                // consecutive transitions in the same direction
+                self.speed = if self.direction == step {
+                    travelled / self.elapsed
+                } else {
+                    0.0
+                };
+                self.direction = step;
+                self.edge_angle = edge_angle;
+                self.limit =
+                    crate::wrap_angle_difference(self.boundary(sector, step) - edge_angle).abs();
+                self.elapsed = 0.0;
+            }
+            None => {
+                self.direction = 0;
+                self.speed = 0.0;
+                self.elapsed = 0.0;
+            }
+        }
+        self.state = Some(state);
+
+        if self.elapsed > self.timeout {
+            self.direction = 0;
+            self.speed = 0.0;
+        }
+
+        Ok(self.angle())
+    }
+
+    /// Angle of the edge between the given sector and the next sector in the
+    /// given direction.
+    fn boundary(&self, sector: u8, step: i8) -> f32 {
+        let centre = self.angles[sector as usize];
+        let next = self.angles[((sector as i8 + step + 6) % 6) as usize];
+
+        crate::wrap_angle(centre + crate::wrap_angle_difference(next - centre) / 2.0)
+    }
+
+    /// Interpolated electrical angle in radians, between 0 and 2π.
+    ///
+    /// Between transitions the angle is extrapolated from the last edge using
+    /// the measured speed, without passing the far edge of the sector. When
+    /// the speed is unknown the angle is the centre of the current sector.
+    pub fn angle(&self) -> f32 {
+        let Some(state) = self.state else {
+            return 0.0;
+        };
+
+        if self.speed == 0.0 {
+            let sector = SECTORS[state as usize].unwrap_or_default();
+            return crate::wrap_angle(self.angles[sector as usize]);
+        }
+
+        let travelled = (self.speed * self.elapsed).clamp(-self.limit, self.limit);
+        crate::wrap_angle(self.edge_angle + travelled)
+    }
+
+    /// Measured electrical speed in radians per second.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Current sector between 0 and 5, if a valid state has been seen.
+    pub fn sector(&self) -> Option<u8> {
+        self.state.and_then(|state| SECTORS[state as usize])
+    }
+
+    /// Voltage vector for six-step commutation, to be used with
+    /// [`Trapezoidal`](crate::pwm::Trapezoidal).
+    ///
+    /// The vector is placed 90° ahead of the centre of the current sector, so
+    /// a positive magnitude produces positive torque.
+    pub fn commutation(&self, magnitude: f32) -> Option<TwoPhaseReferenceFrame> {
+        let sector = self.sector()?;
+        let angle = self.angles[sector as usize] + core::f32::consts::FRAC_PI_2;
+        let (sin_angle, cos_angle) = libm::sincosf(angle);
+
+        Some(TwoPhaseReferenceFrame {
+            alpha: magnitude * cos_angle,
+            beta: magnitude * sin_angle,
+        })
+    }
+}
+
+/// Learns the electrical angle at the centre of each sector.
+///
+/// While the motor is rotated slowly in open loop with a known electrical
+/// angle, feed the angle along with the Hall state. The centre of each sector
+/// is taken as the circular mean of the angles seen in that sector.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HallCalibration {
+    sums: [(f32, f32); 6],
+    counts: [u32; 6],
+}
+
+impl HallCalibration {
+    /// Create a new calibration with no samples.
+    pub const fn new() -> Self {
+        Self {
+            sums: [(0.0, 0.0); 6],
+            counts: [0; 6],
+        }
+    }
+
+    /// Record the Hall state seen at the given electrical angle.
+    pub fn update(&mut self, state: u8, angle: f32) -> Result<(), HallError> {
+        let sector = sector(state)? as usize;
+        let (sin_angle, cos_angle) = libm::sincosf(angle);
+
+        self.sums[sector].0 += sin_angle;
+        self.sums[sector].1 += cos_angle;
+        self.counts[sector] += 1;
+
+        Ok(())
+    }
+
+    /// The learned angle at the centre of each sector, or `None` if any
+    /// sector has not been seen yet.
+    pub fn angles(&self) -> Option<[f32; 6]> {
+        if self.counts.contains(&0) {
+            return None;
+        }
+
+        Some(
+            self.sums
+                .map(|(sin_sum, cos_sum)| crate::wrap_angle(libm::atan2f(sin_sum, cos_sum))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pwm::{Modulation, Trapezoidal};
+    use core::f32::consts::{FRAC_PI_3, TAU};
+
+    const DT: f32 = 0.0001;
+    const OFFSET: f32 = 0.3;
+
+    /// Hall state for each sector, in the order visited for positive rotation.
+    const STATES: [u8; 6] = [0b001, 0b011, 0b010, 0b110, 0b100, 0b101];
+
+    /// Hall state of ideal sensors at the given electrical angle.
+    fn state_at(angle: f32) -> u8 {
+        let sector = libm::floorf(crate::wrap_angle(angle - OFFSET + FRAC_PI_3 / 2.0) / FRAC_PI_3);
+        STATES[sector as usize % 6]
+    }
+
+    #[test]
+    fn decodes_sectors() {
+        for (expected, state) in STATES.into_iter().enumerate() {
+            assert_eq!(sector(state), Ok(expected as u8));
+        }
+
+        assert_eq!(sector(0b000), Err(HallError::InvalidState(0b000)));
+        assert_eq!(sector(0b111), Err(HallError::InvalidState(0b111)));
+        assert_eq!(sector(0b1000), Err(HallError::InvalidState(0b1000)));
+    }
+
+    #[test]
+    fn detects_sequence_error() {
+        let mut hall = HallSensor::new(evenly_spaced_angles(OFFSET), 0.1);
+
+        assert!(hall.update(0b001, DT).is_ok());
+        assert!(hall.update(0b011, DT).is_ok());
+        assert_eq!(
+            hall.update(0b100, DT),
+            Err(HallError::SequenceError {
+                previous: 0b011,
+                current: 0b100
+            })
+        );
+
+        // Resynchronises on the next valid state
+        assert!(hall.update(0b100, DT).is_ok());
+        assert_eq!(hall.sector(), Some(4));
+    }
+
+    #[test]
+    fn detects_invalid_state() {
+        let mut hall = HallSensor::new(evenly_spaced_angles(OFFSET), 0.1);
+
+        assert_eq!(hall.update(0b111, DT), Err(HallError::InvalidState(0b111)));
+        assert_eq!(hall.sector(), None);
+    }
+
+    #[track_caller]
+    fn assert_interpolates(speed: f32) {
+        let mut hall = HallSensor::new(evenly_spaced_angles(OFFSET), 0.1);
+        let mut worst_error: f32 = 0.0;
+
+        for step in 0..10_000 {
+            let angle = crate::wrap_angle(1.0 + speed * step as f32 * DT);
+            let estimate = hall.update(state_at(angle), DT).unwrap();
+
+            if step > 5_000 {
+                let error = crate::wrap_angle_difference(estimate - angle);
+                worst_error = worst_error.max(error.abs());
+            }
+        }
+
+        assert!(worst_error < 0.05, "{worst_error}");
+        assert!((hall.speed() - speed).abs() < 0.01 * speed.abs());
+    }
+
+    #[test]
+    fn interpolates_forwards() {
+        assert_interpolates(200.0);
+    }
+
+    #[test]
+    fn interpolates_backwards() {
+        assert_interpolates(-150.0);
+    }
+
+    #[test]
+    fn holds_sector_centre_at_standstill() {
+        let mut hall = HallSensor::new(evenly_spaced_angles(OFFSET), 0.1);
+
+        for _ in 0..2000 {
+            hall.update(0b011, DT).unwrap();
+        }
+
+        assert_eq!(hall.speed(), 0.0);
+        assert!((hall.angle() - (OFFSET + FRAC_PI_3)).abs() < 0.0001);
+    }
+
+    #[test]
+    fn learns_angles() {
+        let mut calibration = HallCalibration::new();
+        assert_eq!(calibration.angles(), None);
+
+        for step in 0..1000 {
+            let angle = crate::wrap_angle(step as f32 * TAU / 500.0);
+            calibration.update(state_at(angle), angle).unwrap();
+        }
+
+        let angles = calibration.angles().unwrap();
+        for (learned, expected) in angles.into_iter().zip(evenly_spaced_angles(OFFSET)) {
+            assert!(crate::wrap_angle_difference(learned - expected).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn six_step_commutation() {
+        let mut hall = HallSensor::new(evenly_spaced_angles(0.2), 0.1);
+        let mut patterns = [[0.0; 3]; 6];
+
+        for (sector, state) in STATES.into_iter().enumerate() {
+            hall.update(state, DT).unwrap();
+            patterns[sector] = Trapezoidal::modulate(hall.commutation(1.0).unwrap());
+        }
+
+        // Every sector drives a different pattern, with the positive phase
+        // leading the rotor
+        for (sector, pattern) in patterns.iter().enumerate() {
+            let next = &patterns[(sector + 1) % 6];
+            assert_ne!(pattern, next);
+        }
+        assert_eq!(patterns[0], [-1.0, 1.0, -1.0]);
+    }
+}
@@ -6,12 +6,49 @@
 //! ## Feature flags
 #![doc = document_features::document_features!(feature_label = r#"<span class="stab portability"><code>{feature}</code></span>"#)]
 
+pub mod angle;
+pub mod current_calibration;
+pub mod current_loop;
+pub mod dead_time;
+pub mod encoder;
+pub mod fixed;
+pub mod flux_observer;
+pub mod hall;
+pub mod multi_shunt;
+pub mod num;
+pub mod overmodulation;
 pub mod park_clarke;
 pub mod pid;
+pub mod pll;
 pub mod pwm;
+pub mod resolver;
+pub mod single_shunt;
+pub mod smo;
+pub mod voltage_limit;
+
+#[cfg(test)]
+mod sim;
+
+use core::f32::consts::{PI, TAU};
 
 #[allow(clippy::excessive_precision)]
 const FRAC_1_SQRT_3: f32 = 0.577350269189625764509148780501957456_f32;
 
 #[allow(clippy::excessive_precision)]
 const SQRT_3: f32 = 1.732050807568877293527446341505872367_f32;
+
+/// Wrap an angle in radians to the range [0, 2π).
+fn wrap_angle(angle: f32) -> f32 {
+    let wrapped = libm::fmodf(angle, TAU);
+    if wrapped < 0.0 {
+        // Adding 2π to a tiny negative value can round up to 2π
+        (wrapped + TAU) % TAU
+    } else {
+        wrapped
+    }
+}
+
+/// Wrap a difference between two angles in radians to the range [-π, π).
+fn wrap_angle_difference(difference: f32) -> f32 {
+    wrap_angle(difference + PI) - PI
+}
@@ -0,0 +1,262 @@
+//! Phase current measurement from two or three low-side shunts.
+//!
+//! A low-side shunt only carries the phase current while the low-side switch
+//! of its phase is on, so the phase with the highest duty cycle has the
+//! shortest window in which to sample. [`MultiShunt`] selects the phases to
+//! use for each sample from the duty cycles, reconstructs any remaining phase
+//! from the currents summing to zero, and applies the calibration of each
+//! channel.
+
+use crate::park_clarke::ThreePhaseBalancedReferenceFrame;
+
+/// Correction for the offset and gain of a current sensing channel.
+///
+/// The corrected current is `(raw - offset) * gain`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelCalibration {
+    /// Raw value measured at zero current
+    pub offset: f32,
+    /// Current per unit of raw value
+    pub gain: f32,
+}
+
+impl ChannelCalibration {
+    /// Calibration for an ideal channel, with no correction applied.
+    pub const IDEAL: Self = Self {
+        offset: 0.0,
+        gain: 1.0,
+    };
+
+    /// Convert a raw sample to a current.
+    pub fn apply(&self, raw: f32) -> f32 {
+        (raw - self.offset) * self.gain
+    }
+}
+
+impl Default for ChannelCalibration {
+    fn default() -> Self {
+        Self::IDEAL
+    }
+}
+
+/// The phases sampled for a single measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ShuntSelection {
+    /// All three phases are sampled
+    All,
+    /// Phases b and c are sampled, and a is reconstructed
+    ReconstructA,
+    /// Phases a and c are sampled, and b is reconstructed
+    ReconstructB,
+    /// Phases a and b are sampled, and c is reconstructed
+    ReconstructC,
+}
+
+impl ShuntSelection {
+    /// Whether each phase is sampled.
+    pub fn sampled(&self) -> [bool; 3] {
+        match self {
+            ShuntSelection::All => [true; 3],
+            ShuntSelection::ReconstructA => [false, true, true],
+            ShuntSelection::ReconstructB => [true, false, true],
+            ShuntSelection::ReconstructC => [true, true, false],
+        }
+    }
+}
+
+/// Current measurement from low-side shunts on two or three phases.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MultiShunt {
+    calibration: [ChannelCalibration; 3],
+    three_shunts: bool,
+    maximum_duty: f32,
+}
+
+impl MultiShunt {
+    /// Create a new measurement from shunts on phases a and b.
+    pub const fn two_shunt() -> Self {
+        Self {
+            calibration: [
+                ChannelCalibration::IDEAL,
+                ChannelCalibration::IDEAL,
+                ChannelCalibration::IDEAL,
+            ],
+            three_shunts: false,
+            maximum_duty: 1.0,
+        }
+    }
+
+    /// Create a new measurement from shunts on all three phases.
+    ///
+    /// All three phases are sampled while every duty cycle is at most
+    /// `maximum_duty`, otherwise the phase with the highest duty cycle is
+    /// reconstructed from the other two.
+    pub const fn three_shunt(maximum_duty: f32) -> Self {
+        Self {
+            calibration: [
+                ChannelCalibration::IDEAL,
+                ChannelCalibration::IDEAL,
+                ChannelCalibration::IDEAL,
+            ],
+            three_shunts: true,
+            maximum_duty,
+        }
+    }
+
+    /// Set the calibration of the channel for each phase.
+    ///
+    /// The calibration of phase c is unused with two shunts.
+    pub const fn with_calibration(mut self, calibration: [ChannelCalibration; 3]) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    /// Select the phases to sample in the PWM period with the given duty
+    /// cycles, between 0 and 1.
+    pub fn select(&self, duty: [f32; 3]) -> ShuntSelection {
+        if !self.three_shunts {
+            return ShuntSelection::ReconstructC;
+        }
+
+        let highest = if duty[0] >= duty[1] && duty[0] >= duty[2] {
+            0
+        } else if duty[1] >= duty[2] {
+            1
+        } else {
+            2
+        };
+
+        match highest {
+            _ if duty[highest] <= self.maximum_duty => ShuntSelection::All,
+            0 => ShuntSelection::ReconstructA,
+            1 => ShuntSelection::ReconstructB,
+            _ => ShuntSelection::ReconstructC,
+        }
+    }
+
+    /// Convert the raw samples of each phase into phase currents.
+    ///
+    /// The samples of phases not sampled by `selection` are ignored. When all
+    /// three phases are sampled, their common mode is removed.
+    pub fn reconstruct(
+        &self,
+        selection: ShuntSelection,
+        raw: [f32; 3],
+    ) -> ThreePhaseBalancedReferenceFrame {
+        let mut currents = [0.0; 3];
+        for ((current, calibration), raw) in currents.iter_mut().zip(&self.calibration).zip(raw) {
+            *current = calibration.apply(raw);
+        }
+        let [a, b, c] = currents;
+
+        match selection {
+            ShuntSelection::All => {
+                let common = (a + b + c) / 3.0;
+                ThreePhaseBalancedReferenceFrame {
+                    a: a - common,
+                    b: b - common,
+                }
+            }
+            ShuntSelection::ReconstructA => ThreePhaseBalancedReferenceFrame { a: -b - c, b },
+            ShuntSelection::ReconstructB => ThreePhaseBalancedReferenceFrame { a, b: -a - c },
+            ShuntSelection::ReconstructC => ThreePhaseBalancedReferenceFrame { a, b },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALIBRATION: [ChannelCalibration; 3] = [
+        ChannelCalibration {
+            offset: 2048.0,
+            gain: 0.01,
+        },
+        ChannelCalibration {
+            offset: 2010.0,
+            gain: 0.0105,
+        },
+        ChannelCalibration {
+            offset: 2100.0,
+            gain: 0.0095,
+        },
+    ];
+
+    /// Raw samples for the given phase currents.
+    fn raw(currents: [f32; 3]) -> [f32; 3] {
+        let mut raw = [0.0; 3];
+        for ((raw, calibration), current) in raw.iter_mut().zip(&CALIBRATION).zip(currents) {
+            *raw = current / calibration.gain + calibration.offset;
+        }
+        raw
+    }
+
+    #[test]
+    fn selects_phase_with_highest_duty() {
+        let shunt = MultiShunt::three_shunt(0.9);
+
+        assert_eq!(shunt.select([0.5, 0.6, 0.4]), ShuntSelection::All);
+        assert_eq!(
+            shunt.select([0.95, 0.5, 0.05]),
+            ShuntSelection::ReconstructA
+        );
+        assert_eq!(
+            shunt.select([0.5, 0.95, 0.05]),
+            ShuntSelection::ReconstructB
+        );
+        assert_eq!(
+            shunt.select([0.05, 0.5, 0.95]),
+            ShuntSelection::ReconstructC
+        );
+        assert_eq!(ShuntSelection::ReconstructB.sampled(), [true, false, true]);
+    }
+
+    #[test]
+    fn two_shunt_reconstructs_c() {
+        let shunt = MultiShunt::two_shunt().with_calibration(CALIBRATION);
+        let selection = shunt.select([0.05, 0.5, 0.95]);
+        assert_eq!(selection, ShuntSelection::ReconstructC);
+
+        let currents = shunt.reconstruct(selection, raw([3.0, -1.0, -2.0]));
+        assert!((currents.a - 3.0).abs() < 1e-4);
+        assert!((currents.b + 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn applies_calibration() {
+        let shunt = MultiShunt::three_shunt(0.9).with_calibration(CALIBRATION);
+        let currents = [3.0, -1.0, -2.0];
+
+        for selection in [
+            ShuntSelection::All,
+            ShuntSelection::ReconstructA,
+            ShuntSelection::ReconstructB,
+            ShuntSelection::ReconstructC,
+        ] {
+            let mut raw = raw(currents);
+            // The samples of phases that are not sampled are invalid
+            for (raw, sampled) in raw.iter_mut().zip(selection.sampled()) {
+                if !sampled {
+                    *raw = 0.0;
+                }
+            }
+
+            let reconstructed = shunt.reconstruct(selection, raw);
+            assert!((reconstructed.a - 3.0).abs() < 1e-4, "{selection:?}");
+            assert!((reconstructed.b + 1.0).abs() < 1e-4, "{selection:?}");
+        }
+    }
+
+    #[test]
+    fn removes_common_mode() {
+        let shunt = MultiShunt::three_shunt(0.9);
+        let currents = shunt.reconstruct(ShuntSelection::All, [3.3, -0.7, -1.7]);
+
+        assert!((currents.a - 3.0).abs() < 1e-6);
+        assert!((currents.b + 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,85 @@
+//! Numeric types that the transforms and controllers can operate on.
+//!
+//! [`Number`] is implemented for `f32`, `f64` and the fixed-point types in
+//! [`fixed`](crate::fixed), so the same control code can run in a
+//! double-precision simulation and on a microcontroller without an FPU.
+
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A number type supporting the arithmetic needed by the transforms and
+/// controllers.
+pub trait Number:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    /// Zero
+    const ZERO: Self;
+    /// The lowest value, which is negative infinity for floating-point types
+    const MIN: Self;
+    /// The highest value, which is infinity for floating-point types
+    const MAX: Self;
+    /// One half
+    const HALF: Self;
+    /// 1/√3
+    const FRAC_1_SQRT_3: Self;
+    /// √3/2
+    const FRAC_SQRT_3_2: Self;
+
+    /// Convert from an `f32`, saturating outside the representable range.
+    fn from_f32(value: f32) -> Self;
+
+    /// Convert to an `f32`.
+    fn to_f32(self) -> f32;
+}
+
+impl Number for f32 {
+    const ZERO: Self = 0.0;
+    const MIN: Self = f32::NEG_INFINITY;
+    const MAX: Self = f32::INFINITY;
+    const HALF: Self = 0.5;
+    const FRAC_1_SQRT_3: Self = crate::FRAC_1_SQRT_3;
+    const FRAC_SQRT_3_2: Self = crate::SQRT_3 / 2.0;
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl Number for f64 {
+    const ZERO: Self = 0.0;
+    const MIN: Self = f64::NEG_INFINITY;
+    const MAX: Self = f64::INFINITY;
+    const HALF: Self = 0.5;
+    #[allow(clippy::excessive_precision)]
+    const FRAC_1_SQRT_3: Self = 0.577350269189625764509148780501957456_f64;
+    #[allow(clippy::excessive_precision)]
+    const FRAC_SQRT_3_2: Self = 0.866025403784438646763723170752936183_f64;
+
+    fn from_f32(value: f32) -> Self {
+        value as f64
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+/// Restrict a value to the given range.
+pub(crate) fn clamp<T: Number>(value: T, min: T, max: T) -> T {
+    if value < min {
+        min
+    } else if value > max {
+        max
+    } else {
+        value
+    }
+}
@@ -0,0 +1,314 @@
+//! Overmodulation for space-vector PWM.
+//!
+//! [`SpaceVector`](crate::pwm::SpaceVector) is linear for inputs within the
+//! inscribed circle of the voltage hexagon, which has a radius of 1. Beyond
+//! this the compare values are simply clamped, which distorts the angle of the
+//! applied vector. [`Overmodulation`] limits the input to the hexagon first,
+//! so it can be used as
+//! `SpaceVector::as_compare_value(overmodulation.apply(value), max)`.
+
+use core::f32::consts::{FRAC_PI_3, FRAC_PI_6, TAU};
+
+use crate::{park_clarke::TwoPhaseReferenceFrame, pwm::space_vector, FRAC_1_SQRT_3, SQRT_3};
+
+/// Magnitude of the vertices of the voltage hexagon.
+const VERTEX: f32 = 2.0 * FRAC_1_SQRT_3;
+
+/// Radius of the circle that is clipped to the hexagon in mode I, for evenly
+/// spaced fundamental magnitudes from 1 to [`Overmodulation::MODE_I_LIMIT`].
+const MODE_I_RADIUS: [f32; 17] = [
+    1.0, 1.00343, 1.007246, 1.011388, 1.015855, 1.020664, 1.025846, 1.031441, 1.037509, 1.044129,
+    1.051414, 1.059527, 1.068722, 1.079423, 1.092467, 1.110005, VERTEX,
+];
+
+/// Angle for which the vector is held at each vertex in mode II, for evenly
+/// spaced fundamental magnitudes from [`Overmodulation::MODE_I_LIMIT`] to
+/// [`Overmodulation::SIX_STEP_LIMIT`].
+const MODE_II_HOLD_ANGLE: [f32; 17] = [
+    0.0, 0.016854, 0.034256, 0.052267, 0.07096, 0.090423, 0.110765, 0.132124, 0.154677, 0.178656,
+    0.204385, 0.232326, 0.263191, 0.298178, 0.339624, 0.393566, FRAC_PI_6,
+];
+
+/// Outward normals of the edges of the hexagon, as (alpha, beta) pairs.
+pub(crate) const EDGE_NORMALS: [(f32, f32); 6] = [
+    (SQRT_3 / 2.0, 0.5),
+    (0.0, 1.0),
+    (-SQRT_3 / 2.0, 0.5),
+    (-SQRT_3 / 2.0, -0.5),
+    (0.0, -1.0),
+    (SQRT_3 / 2.0, -0.5),
+];
+
+/// Method used to handle space-vector inputs outside the inscribed circle of
+/// the voltage hexagon.
+///
+/// All magnitudes are normalised so that the inscribed circle has a radius of
+/// 1, which corresponds to `Vdc/√3`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Overmodulation {
+    /// No limiting, the compare values are clamped for each phase
+    /// independently.
+    None,
+    /// Scale the vector down onto the hexagon, keeping its angle.
+    MinimumPhaseError,
+    /// Move the vector to the nearest point on the hexagon, keeping as much
+    /// of its magnitude as possible.
+    MinimumMagnitudeError,
+    /// Two-mode overmodulation as described by Bolognani and Holtz, which
+    /// makes the fundamental of the output match the magnitude of the input
+    /// all the way to six-step operation.
+    ///
+    /// In mode I, up to [`Overmodulation::MODE_I_LIMIT`], a circle larger than
+    /// the input is clipped to the hexagon so that the lost magnitude is made
+    /// up near the vertices. In mode II, up to
+    /// [`Overmodulation::SIX_STEP_LIMIT`], the vector follows the hexagon and
+    /// is held at each vertex for an increasing angle, until it only visits
+    /// the six vertices.
+    SixStep,
+}
+
+impl Overmodulation {
+    /// Largest fundamental magnitude reachable in mode I.
+    pub const MODE_I_LIMIT: f32 = 1.049_097_5;
+
+    /// Fundamental magnitude of six-step operation, `2√3/π`.
+    pub const SIX_STEP_LIMIT: f32 = 1.102_657_8;
+
+    /// Limit a space-vector input to the voltage hexagon.
+    pub fn apply(&self, value: TwoPhaseReferenceFrame) -> TwoPhaseReferenceFrame {
+        match self {
+            Overmodulation::None => value,
+            Overmodulation::MinimumPhaseError => minimum_phase_error(value),
+            Overmodulation::MinimumMagnitudeError => minimum_magnitude_error(value),
+            Overmodulation::SixStep => six_step(value),
+        }
+    }
+}
+
+fn minimum_phase_error(value: TwoPhaseReferenceFrame) -> TwoPhaseReferenceFrame {
+    // Space-vector modulation is linear in the magnitude within each sector
+    // and reaches 1 on the hexagon, so the largest output gives the scale
+    let scale = space_vector(value)
+        .into_iter()
+        .fold(0.0_f32, |max, output| max.max(output.abs()));
+
+    if scale > 1.0 {
+        TwoPhaseReferenceFrame {
+            alpha: value.alpha / scale,
+            beta: value.beta / scale,
+        }
+    } else {
+        value
+    }
+}
+
+fn minimum_magnitude_error(value: TwoPhaseReferenceFrame) -> TwoPhaseReferenceFrame {
+    // The edge with the largest projection is the one nearest the vector
+    let (distance, (normal_alpha, normal_beta)) = EDGE_NORMALS
+        .into_iter()
+        .map(|normal| (value.alpha * normal.0 + value.beta * normal.1, normal))
+        .fold((f32::NEG_INFINITY, (0.0, 0.0)), |nearest, edge| {
+            if edge.0 > nearest.0 {
+                edge
+            } else {
+                nearest
+            }
+        });
+
+    if distance <= 1.0 {
+        return value;
+    }
+
+    // Project onto the edge, without going past its vertices
+    let along = (value.beta * normal_alpha - value.alpha * normal_beta)
+        .clamp(-FRAC_1_SQRT_3, FRAC_1_SQRT_3);
+
+    TwoPhaseReferenceFrame {
+        alpha: normal_alpha - along * normal_beta,
+        beta: normal_beta + along * normal_alpha,
+    }
+}
+
+fn six_step(value: TwoPhaseReferenceFrame) -> TwoPhaseReferenceFrame {
+    let magnitude = libm::hypotf(value.alpha, value.beta);
+
+    if magnitude <= 1.0 {
+        return value;
+    }
+
+    if magnitude <= Overmodulation::MODE_I_LIMIT {
+        let position = (magnitude - 1.0) / (Overmodulation::MODE_I_LIMIT - 1.0);
+        let scale = interpolate(&MODE_I_RADIUS, position) / magnitude;
+
+        return minimum_phase_error(TwoPhaseReferenceFrame {
+            alpha: value.alpha * scale,
+            beta: value.beta * scale,
+        });
+    }
+
+    let position = (magnitude - Overmodulation::MODE_I_LIMIT)
+        / (Overmodulation::SIX_STEP_LIMIT - Overmodulation::MODE_I_LIMIT);
+    let hold_angle = interpolate(&MODE_II_HOLD_ANGLE, position);
+
+    let angle = crate::wrap_angle(libm::atan2f(value.beta, value.alpha));
+    let sector = libm::floorf(angle / FRAC_PI_3).min(5.0);
+    let vertex = sector * FRAC_PI_3;
+    let within = angle - vertex;
+
+    let (angle, magnitude) = if within < hold_angle {
+        (vertex, VERTEX)
+    } else if within >= FRAC_PI_3 - hold_angle {
+        (vertex + FRAC_PI_3, VERTEX)
+    } else {
+        // Move along the edge between the vertices at a faster rate
+        let along = (within - hold_angle) * FRAC_PI_3 / (FRAC_PI_3 - 2.0 * hold_angle);
+        (vertex + along, 1.0 / libm::cosf(along - FRAC_PI_6))
+    };
+
+    let (sin_angle, cos_angle) = libm::sincosf(angle % TAU);
+    TwoPhaseReferenceFrame {
+        alpha: magnitude * cos_angle,
+        beta: magnitude * sin_angle,
+    }
+}
+
+/// Linearly interpolate a table of evenly spaced values, where `position`
+/// ranges from 0 to 1.
+fn interpolate(table: &[f32], position: f32) -> f32 {
+    let last = table.len() - 1;
+    let position = position.clamp(0.0, 1.0) * last as f32;
+    let index = (position as usize).min(last - 1);
+    let fraction = position - index as f32;
+
+    table[index] + (table[index + 1] - table[index]) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METHODS: [Overmodulation; 4] = [
+        Overmodulation::None,
+        Overmodulation::MinimumPhaseError,
+        Overmodulation::MinimumMagnitudeError,
+        Overmodulation::SixStep,
+    ];
+
+    fn polar(magnitude: f32, angle: f32) -> TwoPhaseReferenceFrame {
+        let (sin_angle, cos_angle) = libm::sincosf(angle);
+        TwoPhaseReferenceFrame {
+            alpha: magnitude * cos_angle,
+            beta: magnitude * sin_angle,
+        }
+    }
+
+    /// Largest space-vector output, which is 1 on the hexagon.
+    fn peak(value: TwoPhaseReferenceFrame) -> f32 {
+        space_vector(value)
+            .into_iter()
+            .fold(0.0_f32, |max, output| max.max(output.abs()))
+    }
+
+    /// Magnitude of the fundamental of the output over one revolution of an
+    /// input of the given magnitude.
+    fn fundamental(method: Overmodulation, magnitude: f32) -> f32 {
+        let steps = 3600;
+        let sum: f32 = (0..steps)
+            .map(|step| {
+                let angle = step as f32 * TAU / steps as f32;
+                let (sin_angle, cos_angle) = libm::sincosf(angle);
+                let output = method.apply(polar(magnitude, angle));
+                output.alpha * cos_angle + output.beta * sin_angle
+            })
+            .sum();
+
+        sum / steps as f32
+    }
+
+    #[test]
+    fn linear_region_unchanged() {
+        for method in METHODS {
+            for step in 0..100 {
+                let value = polar(0.99, step as f32 * 0.0628);
+                let output = method.apply(value);
+                assert_eq!((output.alpha, output.beta), (value.alpha, value.beta));
+            }
+        }
+    }
+
+    #[test]
+    fn minimum_phase_error_keeps_angle() {
+        for step in 0..100 {
+            let angle = step as f32 * 0.0628;
+            let output = Overmodulation::MinimumPhaseError.apply(polar(1.5, angle));
+
+            let output_angle = libm::atan2f(output.beta, output.alpha);
+            assert!(crate::wrap_angle_difference(output_angle - angle).abs() < 0.0001);
+            assert!((peak(output) - 1.0).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn minimum_magnitude_error_finds_nearest_point() {
+        let output = Overmodulation::MinimumMagnitudeError.apply(TwoPhaseReferenceFrame {
+            alpha: 0.2,
+            beta: 1.5,
+        });
+        assert!((output.alpha - 0.2).abs() < 0.0001);
+        assert!((output.beta - 1.0).abs() < 0.0001);
+
+        // Beyond a vertex the vertex itself is nearest
+        let output = Overmodulation::MinimumMagnitudeError.apply(polar(2.0, 0.0));
+        assert!((output.alpha - VERTEX).abs() < 0.0001);
+        assert!(output.beta.abs() < 0.0001);
+
+        for step in 0..100 {
+            let value = polar(1.3, step as f32 * 0.0628);
+            let nearest = Overmodulation::MinimumMagnitudeError.apply(value);
+            let scaled = Overmodulation::MinimumPhaseError.apply(value);
+            assert!((peak(nearest) - 1.0).abs() < 0.0001);
+
+            let distance = |output: &TwoPhaseReferenceFrame| {
+                libm::hypotf(output.alpha - value.alpha, output.beta - value.beta)
+            };
+            assert!(distance(&nearest) <= distance(&scaled) + 0.0001);
+        }
+    }
+
+    #[test]
+    fn six_step_fundamental_matches_input() {
+        for magnitude in [1.0, 1.01, 1.03, 1.049, 1.05, 1.07, 1.09, 1.1, 1.1026] {
+            let output = fundamental(Overmodulation::SixStep, magnitude);
+            assert!((output - magnitude).abs() < 0.002, "{magnitude}: {output}");
+        }
+
+        // Plain limiting falls short of the requested fundamental
+        let output = fundamental(Overmodulation::MinimumPhaseError, 1.05);
+        assert!(output < 1.04, "{output}");
+    }
+
+    #[test]
+    fn six_step_visits_only_vertices() {
+        for step in 0..100 {
+            let output = Overmodulation::SixStep.apply(polar(1.2, step as f32 * 0.0628));
+            let angle = crate::wrap_angle(libm::atan2f(output.beta, output.alpha));
+
+            assert!((libm::hypotf(output.alpha, output.beta) - VERTEX).abs() < 0.0001);
+            let offset = angle / FRAC_PI_3 - libm::roundf(angle / FRAC_PI_3);
+            assert!(offset.abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn stays_within_hexagon() {
+        for method in METHODS.into_iter().skip(1) {
+            for magnitude in [1.02, 1.06, 1.2, 3.0] {
+                for step in 0..100 {
+                    let output = method.apply(polar(magnitude, step as f32 * 0.0628));
+                    assert!(peak(output) < 1.0001);
+                }
+            }
+        }
+    }
+}
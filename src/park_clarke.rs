@@ -1,89 +1,386 @@
 //! Park and Clarke transformations (along with their inverses).
 //!
 //! The algorithms implemented here are based on [Microsemi's suggested implementation](https://www.microsemi.com/document-portal/doc_view/132799-park-inverse-park-and-clarke-inverse-clarke-transformations-mss-software-implementation-user-guide)
+//!
+//! The reference frames and transforms are generic over the [`Number`] type of
+//! their components, which defaults to `f32`. The transforms are arranged so
+//! that no intermediate value exceeds the magnitude of the inputs by more than
+//! the result does, which keeps them usable with the fractional fixed-point
+//! types in [`fixed`](crate::fixed).
+//!
+//! The transforms use the amplitude-invariant convention, and [`Convention`]
+//! provides the power-invariant alternative.
+
+use core::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use crate::{angle::ElectricalAngle, num::Number};
+
+/// √(3/2)
+#[allow(clippy::excessive_precision)]
+const SQRT_3_2: f32 = 1.224744871391589049098642037352945695_f32;
 
-use crate::{FRAC_1_SQRT_3, SQRT_3};
+/// √(2/3)
+#[allow(clippy::excessive_precision)]
+const FRAC_SQRT_2_SQRT_3: f32 = 0.816496580927726032732428024901963797_f32;
 
 /// A value in a reference frame that moves with the electrical angle of the
 /// motor. The two axes are orthogonal.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct RotatingReferenceFrame {
+pub struct RotatingReferenceFrame<T = f32> {
     /// Direct axis component aligned with the rotor flux
-    pub d: f32,
+    pub d: T,
     /// Quadrature axis component perpendicular to the rotor flux
-    pub q: f32,
+    pub q: T,
 }
 
 /// A value in a reference frame that is stationary. The two axes are
 /// orthogonal.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct TwoPhaseReferenceFrame {
+pub struct TwoPhaseReferenceFrame<T = f32> {
     /// Alpha component aligned with phase A
-    pub alpha: f32,
+    pub alpha: T,
     /// Beta component perpendicular to alpha
-    pub beta: f32,
+    pub beta: T,
 }
 
 /// A three-phase value in a stationary reference frame. The values do not
 /// necessarily sum to 0.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct ThreePhaseReferenceFrame {
+pub struct ThreePhaseReferenceFrame<T = f32> {
     /// Phase A component
-    pub a: f32,
+    pub a: T,
     /// Phase B component
-    pub b: f32,
+    pub b: T,
     /// Phase C component
-    pub c: f32,
+    pub c: T,
 }
 
 /// A three-phase value in a stationary reference frame, where the three values
 /// sum to 0. As such, the third value is not given.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct ThreePhaseBalancedReferenceFrame {
+pub struct ThreePhaseBalancedReferenceFrame<T = f32> {
     /// Phase A component
-    pub a: f32,
+    pub a: T,
     /// Phase B component
-    pub b: f32,
+    pub b: T,
+}
+
+/// A value in a stationary reference frame with a zero-sequence component,
+/// for three-phase values that do not necessarily sum to 0.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TwoPhaseZeroSequenceReferenceFrame<T = f32> {
+    /// Alpha component aligned with phase A
+    pub alpha: T,
+    /// Beta component perpendicular to alpha
+    pub beta: T,
+    /// Gamma component, the mean of the three phases
+    pub gamma: T,
+}
+
+/// A value in a reference frame that moves with the electrical angle of the
+/// motor, with a zero-sequence component.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RotatingZeroSequenceReferenceFrame<T = f32> {
+    /// Direct axis component aligned with the rotor flux
+    pub d: T,
+    /// Quadrature axis component perpendicular to the rotor flux
+    pub q: T,
+    /// Zero-sequence component, which does not rotate
+    pub zero: T,
 }
 
 /// Clarke transform
 ///
 /// Implements equations 1-4 from the Microsemi guide.
-pub fn clarke(inputs: ThreePhaseBalancedReferenceFrame) -> TwoPhaseReferenceFrame {
+pub fn clarke<T: Number>(inputs: ThreePhaseBalancedReferenceFrame<T>) -> TwoPhaseReferenceFrame<T> {
+    let b = T::FRAC_1_SQRT_3 * inputs.b;
+
     TwoPhaseReferenceFrame {
         // Eq3
         alpha: inputs.a,
         // Eq4
-        beta: FRAC_1_SQRT_3 * (inputs.a + 2.0 * inputs.b),
+        beta: T::FRAC_1_SQRT_3 * inputs.a + b + b,
     }
 }
 
 /// Inverse Clarke transform
 ///
 /// Implements equations 5-7 from the Microsemi guide.
-pub fn inverse_clarke(inputs: TwoPhaseReferenceFrame) -> ThreePhaseReferenceFrame {
+pub fn inverse_clarke<T: Number>(inputs: TwoPhaseReferenceFrame<T>) -> ThreePhaseReferenceFrame<T> {
+    let alpha = T::HALF * inputs.alpha;
+    let beta = T::FRAC_SQRT_3_2 * inputs.beta;
+
     ThreePhaseReferenceFrame {
         // Eq5
         a: inputs.alpha,
         // Eq6
-        b: (-inputs.alpha + SQRT_3 * inputs.beta) / 2.0,
+        b: beta - alpha,
         // Eq7
-        c: (-inputs.alpha - SQRT_3 * inputs.beta) / 2.0,
+        c: -alpha - beta,
+    }
+}
+
+/// Implement vector arithmetic for a two-component reference frame.
+macro_rules! vector {
+    ($name:ident, $x:ident, $y:ident) => {
+        impl<T: Number> $name<T> {
+            /// Dot product with another value.
+            pub fn dot(self, other: Self) -> T {
+                self.$x * other.$x + self.$y * other.$y
+            }
+
+            /// Magnitude of the cross product with another value, which is
+            /// positive when `other` is anticlockwise of `self`.
+            pub fn cross(self, other: Self) -> T {
+                self.$x * other.$y - self.$y * other.$x
+            }
+        }
+
+        impl $name {
+            /// Create a value from its magnitude and angle in radians.
+            pub fn from_polar(magnitude: f32, angle: f32) -> Self {
+                let (sin_angle, cos_angle) = libm::sincosf(angle);
+                Self {
+                    $x: magnitude * cos_angle,
+                    $y: magnitude * sin_angle,
+                }
+            }
+
+            /// Magnitude of the value.
+            pub fn magnitude(self) -> f32 {
+                libm::hypotf(self.$x, self.$y)
+            }
+
+            /// Angle of the value in radians, in the range [-π, π].
+            pub fn angle(self) -> f32 {
+                libm::atan2f(self.$y, self.$x)
+            }
+
+            /// The value scaled to a magnitude of 1, or zero if the magnitude is
+            /// zero.
+            pub fn normalise(self) -> Self {
+                let magnitude = self.magnitude();
+                if magnitude > 0.0 {
+                    self * (1.0 / magnitude)
+                } else {
+                    Self::default()
+                }
+            }
+
+            /// The value scaled down to the given magnitude if it is larger,
+            /// keeping its angle.
+            pub fn limit_magnitude(self, max: f32) -> Self {
+                let magnitude = self.magnitude();
+                if magnitude > max {
+                    self * (max / magnitude)
+                } else {
+                    self
+                }
+            }
+        }
+
+        impl<T: Number> Add for $name<T> {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self {
+                Self {
+                    $x: self.$x + rhs.$x,
+                    $y: self.$y + rhs.$y,
+                }
+            }
+        }
+
+        impl<T: Number> Sub for $name<T> {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self {
+                Self {
+                    $x: self.$x - rhs.$x,
+                    $y: self.$y - rhs.$y,
+                }
+            }
+        }
+
+        impl<T: Number> Mul<T> for $name<T> {
+            type Output = Self;
+
+            fn mul(self, rhs: T) -> Self {
+                Self {
+                    $x: self.$x * rhs,
+                    $y: self.$y * rhs,
+                }
+            }
+        }
+
+        impl<T: Number> Neg for $name<T> {
+            type Output = Self;
+
+            fn neg(self) -> Self {
+                Self {
+                    $x: -self.$x,
+                    $y: -self.$y,
+                }
+            }
+        }
+
+        impl<T: Number> AddAssign for $name<T> {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl<T: Number> SubAssign for $name<T> {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl<T: Number> MulAssign<T> for $name<T> {
+            fn mul_assign(&mut self, rhs: T) {
+                *self = *self * rhs;
+            }
+        }
+    };
+}
+
+vector!(TwoPhaseReferenceFrame, alpha, beta);
+vector!(RotatingReferenceFrame, d, q);
+
+/// Scaling convention of the Clarke transform.
+///
+/// [`clarke`] and [`inverse_clarke`] use the amplitude-invariant convention,
+/// where the magnitude of the two-phase value equals the peak phase value.
+/// Some simulation tools and datasheets instead use the power-invariant
+/// convention, which scales the two-phase value by √(3/2) so that power is
+/// calculated the same way in both reference frames.
+///
+/// Resistances and inductances are the same in both conventions, while
+/// voltages, currents and flux linkages are converted with
+/// [`scale_to`](Self::scale_to).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Convention {
+    /// The magnitude of the two-phase value equals the peak phase value
+    AmplitudeInvariant,
+    /// The power is the dot product of the voltage and current in the
+    /// two-phase and rotating reference frames
+    PowerInvariant,
+}
+
+impl Convention {
+    /// Clarke transform using this convention.
+    pub fn clarke(&self, inputs: ThreePhaseBalancedReferenceFrame) -> TwoPhaseReferenceFrame {
+        let scale = Self::AmplitudeInvariant.scale_to(*self);
+        let value = clarke(inputs);
+
+        TwoPhaseReferenceFrame {
+            alpha: value.alpha * scale,
+            beta: value.beta * scale,
+        }
+    }
+
+    /// Inverse Clarke transform using this convention.
+    pub fn inverse_clarke(&self, inputs: TwoPhaseReferenceFrame) -> ThreePhaseReferenceFrame {
+        let scale = self.scale_to(Self::AmplitudeInvariant);
+
+        inverse_clarke(TwoPhaseReferenceFrame {
+            alpha: inputs.alpha * scale,
+            beta: inputs.beta * scale,
+        })
+    }
+
+    /// Factor to convert a voltage, current or flux linkage from this
+    /// convention to another.
+    pub fn scale_to(&self, to: Convention) -> f32 {
+        match (self, to) {
+            (Self::AmplitudeInvariant, Self::PowerInvariant) => SQRT_3_2,
+            (Self::PowerInvariant, Self::AmplitudeInvariant) => FRAC_SQRT_2_SQRT_3,
+            _ => 1.0,
+        }
+    }
+
+    /// Electrical power from a voltage and current in the rotating reference
+    /// frame.
+    pub fn power(&self, voltage: &RotatingReferenceFrame, current: &RotatingReferenceFrame) -> f32 {
+        self.power_scale() * (voltage.d * current.d + voltage.q * current.q)
+    }
+
+    /// Torque produced by a current in the rotating reference frame, given
+    /// the flux linkage of the permanent magnets and the inductances of each
+    /// axis.
+    ///
+    /// The flux linkage and current must use this convention.
+    pub fn torque(
+        &self,
+        pole_pairs: u8,
+        flux_linkage: f32,
+        d_inductance: f32,
+        q_inductance: f32,
+        current: &RotatingReferenceFrame,
+    ) -> f32 {
+        let reluctance = (d_inductance - q_inductance) * current.d * current.q;
+
+        self.power_scale() * pole_pairs as f32 * (flux_linkage * current.q + reluctance)
+    }
+
+    /// Ratio of the power to the dot product of voltage and current.
+    fn power_scale(&self) -> f32 {
+        match self {
+            Self::AmplitudeInvariant => 1.5,
+            Self::PowerInvariant => 1.0,
+        }
+    }
+}
+
+/// Clarke transform of three phases that do not necessarily sum to 0
+///
+/// Alpha and beta match [`clarke`] for balanced inputs, with the mean of the
+/// three phases given as the gamma component.
+pub fn clarke_zero_sequence<T: Number>(
+    inputs: ThreePhaseReferenceFrame<T>,
+) -> TwoPhaseZeroSequenceReferenceFrame<T> {
+    let third = T::FRAC_1_SQRT_3 * T::FRAC_1_SQRT_3;
+    let gamma = third * inputs.a + third * inputs.b + third * inputs.c;
+
+    TwoPhaseZeroSequenceReferenceFrame {
+        alpha: inputs.a - gamma,
+        beta: T::FRAC_1_SQRT_3 * inputs.b - T::FRAC_1_SQRT_3 * inputs.c,
+        gamma,
+    }
+}
+
+/// Inverse Clarke transform including the zero-sequence component
+///
+/// Matches [`inverse_clarke`] with gamma added to each phase.
+pub fn inverse_clarke_zero_sequence<T: Number>(
+    inputs: TwoPhaseZeroSequenceReferenceFrame<T>,
+) -> ThreePhaseReferenceFrame<T> {
+    let phases = inverse_clarke(TwoPhaseReferenceFrame {
+        alpha: inputs.alpha,
+        beta: inputs.beta,
+    });
+
+    ThreePhaseReferenceFrame {
+        a: phases.a + inputs.gamma,
+        b: phases.b + inputs.gamma,
+        c: phases.c + inputs.gamma,
     }
 }
 
 /// Park transform
 ///
 /// Implements equations 8 and 9 from the Microsemi guide.
-pub fn park(
-    cos_angle: f32,
-    sin_angle: f32,
-    inputs: TwoPhaseReferenceFrame,
-) -> RotatingReferenceFrame {
+pub fn park<T: Number>(
+    cos_angle: T,
+    sin_angle: T,
+    inputs: TwoPhaseReferenceFrame<T>,
+) -> RotatingReferenceFrame<T> {
     RotatingReferenceFrame {
         // Eq8
         d: cos_angle * inputs.alpha + sin_angle * inputs.beta,
@@ -95,11 +392,11 @@ pub fn park(
 /// Inverse Park transform
 ///
 /// Implements equations 10 and 11 from the Microsemi guide.
-pub fn inverse_park(
-    cos_angle: f32,
-    sin_angle: f32,
-    inputs: RotatingReferenceFrame,
-) -> TwoPhaseReferenceFrame {
+pub fn inverse_park<T: Number>(
+    cos_angle: T,
+    sin_angle: T,
+    inputs: RotatingReferenceFrame<T>,
+) -> TwoPhaseReferenceFrame<T> {
     TwoPhaseReferenceFrame {
         // Eq10
         alpha: cos_angle * inputs.d - sin_angle * inputs.q,
@@ -108,6 +405,69 @@ pub fn inverse_park(
     }
 }
 
+/// Park transform including the zero-sequence component, which is passed
+/// through unchanged.
+pub fn park_zero_sequence<T: Number>(
+    cos_angle: T,
+    sin_angle: T,
+    inputs: TwoPhaseZeroSequenceReferenceFrame<T>,
+) -> RotatingZeroSequenceReferenceFrame<T> {
+    let rotating = park(
+        cos_angle,
+        sin_angle,
+        TwoPhaseReferenceFrame {
+            alpha: inputs.alpha,
+            beta: inputs.beta,
+        },
+    );
+
+    RotatingZeroSequenceReferenceFrame {
+        d: rotating.d,
+        q: rotating.q,
+        zero: inputs.gamma,
+    }
+}
+
+/// Inverse Park transform including the zero-sequence component, which is
+/// passed through unchanged.
+pub fn inverse_park_zero_sequence<T: Number>(
+    cos_angle: T,
+    sin_angle: T,
+    inputs: RotatingZeroSequenceReferenceFrame<T>,
+) -> TwoPhaseZeroSequenceReferenceFrame<T> {
+    let stationary = inverse_park(
+        cos_angle,
+        sin_angle,
+        RotatingReferenceFrame {
+            d: inputs.d,
+            q: inputs.q,
+        },
+    );
+
+    TwoPhaseZeroSequenceReferenceFrame {
+        alpha: stationary.alpha,
+        beta: stationary.beta,
+        gamma: inputs.zero,
+    }
+}
+
+/// Park transform using the sine and cosine cached in an [`ElectricalAngle`].
+pub fn park_with_angle(
+    angle: &ElectricalAngle,
+    inputs: TwoPhaseReferenceFrame,
+) -> RotatingReferenceFrame {
+    park(angle.cos(), angle.sin(), inputs)
+}
+
+/// Inverse Park transform using the sine and cosine cached in an
+/// [`ElectricalAngle`].
+pub fn inverse_park_with_angle(
+    angle: &ElectricalAngle,
+    inputs: RotatingReferenceFrame,
+) -> TwoPhaseReferenceFrame {
+    inverse_park(angle.cos(), angle.sin(), inputs)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -115,7 +475,7 @@ mod tests {
     #[track_caller]
     fn clark_e_round_trip(a: f32, b: f32) {
         let input = ThreePhaseBalancedReferenceFrame { a, b };
-        let two_phase = clarke(input.clone());
+        let two_phase = clarke(input);
         let result = inverse_clarke(two_phase);
 
         assert!((result.a - input.a).abs() < 0.0001);
@@ -145,10 +505,185 @@ mod tests {
             alpha: 2.0,
             beta: 3.0,
         };
-        let moving_reference = park(cos_angle, sin_angle, input.clone());
+        let moving_reference = park(cos_angle, sin_angle, input);
         let result = inverse_park(cos_angle, sin_angle, moving_reference);
 
         assert!((result.alpha - input.alpha).abs() < 0.0001);
         assert!((result.beta - input.beta).abs() < 0.0001);
     }
+
+    #[test]
+    fn park_with_angle_matches_park() {
+        let angle = ElectricalAngle::new(4.1);
+        let (sin_angle, cos_angle) = libm::sincosf(4.1);
+
+        let input = TwoPhaseReferenceFrame {
+            alpha: -1.5,
+            beta: 0.7,
+        };
+        let expected = park(cos_angle, sin_angle, input);
+        let result = park_with_angle(&angle, input);
+        assert!((result.d - expected.d).abs() < 0.0001);
+        assert!((result.q - expected.q).abs() < 0.0001);
+
+        let expected = inverse_park(cos_angle, sin_angle, result);
+        let result = inverse_park_with_angle(&angle, result);
+        assert!((result.alpha - expected.alpha).abs() < 0.0001);
+        assert!((result.beta - expected.beta).abs() < 0.0001);
+    }
+
+    #[test]
+    fn generic_over_f64() {
+        let input = ThreePhaseBalancedReferenceFrame {
+            a: 0.3_f64,
+            b: -0.7,
+        };
+        let two_phase = clarke(input);
+        let expected = clarke(ThreePhaseBalancedReferenceFrame {
+            a: 0.3_f32,
+            b: -0.7,
+        });
+        assert!((two_phase.alpha - expected.alpha as f64).abs() < 1e-6);
+        assert!((two_phase.beta - expected.beta as f64).abs() < 1e-6);
+
+        let (sin_angle, cos_angle) = libm::sincos(0.82);
+        let rotating = park(cos_angle, sin_angle, two_phase);
+        let result = inverse_clarke(inverse_park(cos_angle, sin_angle, rotating));
+        assert!((result.a - input.a).abs() < 1e-12);
+        assert!((result.b - input.b).abs() < 1e-12);
+    }
+
+    #[test]
+    fn clarke_zero_sequence_matches_clarke() {
+        let balanced = clarke(ThreePhaseBalancedReferenceFrame {
+            a: 0.3_f32,
+            b: -0.7,
+        });
+        let result = clarke_zero_sequence(ThreePhaseReferenceFrame {
+            a: 0.3,
+            b: -0.7,
+            c: 0.4,
+        });
+
+        assert!((result.alpha - balanced.alpha).abs() < 0.0001);
+        assert!((result.beta - balanced.beta).abs() < 0.0001);
+        assert!(result.gamma.abs() < 0.0001);
+    }
+
+    #[test]
+    fn zero_sequence_round_trip() {
+        let input = ThreePhaseReferenceFrame {
+            a: 0.5_f32,
+            b: -0.2,
+            c: 0.3,
+        };
+        let stationary = clarke_zero_sequence(input);
+        assert!((stationary.gamma - 0.2).abs() < 0.0001);
+
+        let (sin_angle, cos_angle) = libm::sincosf(2.3);
+        let rotating = park_zero_sequence(cos_angle, sin_angle, stationary);
+        assert!((rotating.zero - 0.2).abs() < 0.0001);
+
+        let result = inverse_clarke_zero_sequence(inverse_park_zero_sequence(
+            cos_angle, sin_angle, rotating,
+        ));
+        assert!((result.a - input.a).abs() < 0.0001);
+        assert!((result.b - input.b).abs() < 0.0001);
+        assert!((result.c - input.c).abs() < 0.0001);
+    }
+
+    #[test]
+    fn power_invariant_round_trip() {
+        let input = ThreePhaseBalancedReferenceFrame { a: 0.3, b: -0.7 };
+        let amplitude = clarke(input);
+        let power = Convention::PowerInvariant.clarke(input);
+
+        let magnitude = |value: &TwoPhaseReferenceFrame| libm::hypotf(value.alpha, value.beta);
+        assert!((magnitude(&power) - magnitude(&amplitude) * SQRT_3_2).abs() < 0.0001);
+
+        let result = Convention::PowerInvariant.inverse_clarke(power);
+        assert!((result.a - input.a).abs() < 0.0001);
+        assert!((result.b - input.b).abs() < 0.0001);
+
+        let result = Convention::AmplitudeInvariant.clarke(input);
+        assert!((result.alpha - amplitude.alpha).abs() < 0.0001);
+        assert!((result.beta - amplitude.beta).abs() < 0.0001);
+    }
+
+    #[test]
+    fn power_matches_phases() {
+        let (sin_angle, cos_angle) = libm::sincosf(0.6);
+        let voltage = ThreePhaseBalancedReferenceFrame { a: 10.0, b: -4.0 };
+        let current = ThreePhaseBalancedReferenceFrame { a: 2.0, b: 1.5 };
+        let expected = voltage.a * current.a
+            + voltage.b * current.b
+            + (voltage.a + voltage.b) * (current.a + current.b);
+
+        for convention in [Convention::AmplitudeInvariant, Convention::PowerInvariant] {
+            let voltage = park(cos_angle, sin_angle, convention.clarke(voltage));
+            let current = park(cos_angle, sin_angle, convention.clarke(current));
+            let power = convention.power(&voltage, &current);
+            assert!((power - expected).abs() < 0.001, "{convention:?} {power}");
+        }
+    }
+
+    #[test]
+    fn torque_independent_of_convention() {
+        let amplitude = Convention::AmplitudeInvariant;
+        let power = Convention::PowerInvariant;
+        let current = RotatingReferenceFrame { d: -2.0, q: 5.0 };
+        let expected = amplitude.torque(4, 0.01, 0.0002, 0.0003, &current);
+
+        let scale = amplitude.scale_to(power);
+        let current = RotatingReferenceFrame {
+            d: current.d * scale,
+            q: current.q * scale,
+        };
+        let torque = power.torque(4, 0.01 * scale, 0.0002, 0.0003, &current);
+        assert!((torque - expected).abs() < 0.0001);
+        assert!((power.scale_to(amplitude) * scale - 1.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn vector_arithmetic() {
+        let a = RotatingReferenceFrame { d: 3.0, q: 4.0 };
+        let b = RotatingReferenceFrame { d: -1.0, q: 2.0 };
+
+        assert_eq!(a + b, RotatingReferenceFrame { d: 2.0, q: 6.0 });
+        assert_eq!(a - b, RotatingReferenceFrame { d: 4.0, q: 2.0 });
+        assert_eq!(a * 2.0, RotatingReferenceFrame { d: 6.0, q: 8.0 });
+        assert_eq!(-a, RotatingReferenceFrame { d: -3.0, q: -4.0 });
+        assert_eq!(a.dot(b), 5.0);
+        assert_eq!(a.cross(b), 10.0);
+
+        let mut c = a;
+        c += b;
+        c -= a;
+        c *= 3.0;
+        assert_eq!(c, b * 3.0);
+        assert_eq!(RotatingReferenceFrame::default(), a * 0.0);
+    }
+
+    #[test]
+    fn vector_magnitude() {
+        let a = TwoPhaseReferenceFrame {
+            alpha: 3.0,
+            beta: 4.0,
+        };
+        assert_eq!(a.magnitude(), 5.0);
+        assert!((a.normalise().magnitude() - 1.0).abs() < 0.0001);
+        assert_eq!(
+            TwoPhaseReferenceFrame::default().normalise().magnitude(),
+            0.0
+        );
+
+        let limited = a.limit_magnitude(2.5);
+        assert!((limited.alpha - 1.5).abs() < 0.0001);
+        assert!((limited.beta - 2.0).abs() < 0.0001);
+        assert_eq!(a.limit_magnitude(10.0), a);
+
+        let polar = TwoPhaseReferenceFrame::from_polar(a.magnitude(), a.angle());
+        assert!((polar.alpha - a.alpha).abs() < 0.0001);
+        assert!((polar.beta - a.beta).abs() < 0.0001);
+    }
 }
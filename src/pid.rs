@@ -1,138 +1,661 @@
-//! Floating-point P, PI and PID controllers.
+//! P, PI and PID controllers.
+//!
+//! The controllers are generic over the [`Number`] type used for the gains,
+//! signals and time step, which defaults to `f32`. For the fixed-point types,
+//! where the integral gain times the time step is usually below the
+//! resolution, use [`fixed::PIController`](crate::fixed::PIController).
+//!
+//! The builders that check their arguments are `const` for `f32` and `f64`.
+//! As they are implemented separately for each type, a controller built from
+//! unsuffixed literals needs its type given, as in `PIController::<f32>::new`.
 
-/// A floating-point P controller.
+use crate::{
+    fixed::Fixed,
+    num::{self, Number},
+};
+
+/// Strategy used to stop the integral term from winding up while the
+/// controller output is saturated.
+#[derive(Debug, Clone, Copy, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct PController {
-    p: ProportionalComponent,
+pub enum AntiWindup<T = f32> {
+    /// No anti-windup, the integral term accumulates without bound.
+    None,
+    /// Conditional integration. The integral term is held while the output is
+    /// saturated and the error would drive it further into saturation.
+    Clamping,
+    /// Back-calculation. The amount by which the output exceeds its limits is
+    /// fed back into the integral term through the tracking gain.
+    BackCalculation {
+        /// Tracking gain, a common choice is `k_i / k_p`.
+        tracking_gain: T,
+    },
+    /// The integral term is kept within the given range.
+    IntegratorLimit {
+        /// Lower bound of the integral term
+        min: T,
+        /// Upper bound of the integral term
+        max: T,
+    },
 }
 
-impl PController {
+/// Low-pass filter applied to the derivative term of a [`PIDController`].
+///
+/// The filters are discretised with the backward Euler method, which keeps
+/// them stable for any positive `dt`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DerivativeFilter {
+    /// No filtering, the derivative is the raw finite difference.
+    None,
+    /// First-order low-pass filter.
+    FirstOrder,
+    /// Critically damped second-order low-pass filter, made up of two cascaded
+    /// first-order sections with the same time constant.
+    SecondOrder,
+}
+
+/// A P controller.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PController<T = f32> {
+    p: ProportionalComponent<T>,
+}
+
+impl<T: Number> PController<T> {
     /// Create a new controller with the given gains.
-    pub const fn new(k_p: f32) -> Self {
+    pub const fn new(k_p: T) -> Self {
         let p = ProportionalComponent { gain: k_p };
 
         Self { p }
     }
 
     /// Update the controller, returning the new output value.
-    pub fn update(&mut self, setpoint: f32, measurement: f32) -> f32 {
+    pub fn update(&mut self, setpoint: T, measurement: T) -> T {
         let error = setpoint - measurement;
 
         self.p.update(error)
     }
 }
 
-/// A floating-point PI controller.
+/// A PI controller.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct PIController {
-    p: ProportionalComponent,
-    i: IntegralComponent,
+pub struct PIController<T = f32> {
+    p: ProportionalComponent<T>,
+    i: IntegralComponent<T>,
+    limits: OutputLimits<T>,
 }
 
-impl PIController {
+impl<T: Number> PIController<T> {
     /// Create a new controller with the given gains.
-    pub const fn new(k_p: f32, k_i: f32) -> Self {
+    pub const fn new(k_p: T, k_i: T) -> Self {
         let p = ProportionalComponent { gain: k_p };
 
         let i = IntegralComponent {
             gain: k_i,
-            integral: 0.0,
+            integral: T::ZERO,
+            step: T::ZERO,
+            anti_windup: AntiWindup::None,
         };
 
-        Self { p, i }
+        Self {
+            p,
+            i,
+            limits: OutputLimits::UNBOUNDED,
+        }
     }
 
     /// Update the controller, returning the new output value.
-    pub fn update(&mut self, setpoint: f32, measurement: f32, dt: f32) -> f32 {
+    pub fn update(&mut self, setpoint: T, measurement: T, dt: T) -> T {
         let error = setpoint - measurement;
 
         let p = self.p.update(error);
-        let i = self.i.update(error, dt);
+        let i = self.i.update(error, dt, p, &self.limits);
+
+        self.limits.clamp(p + i)
+    }
 
-        p + i
+    /// Apply the anti-windup strategy to saturation after the controller, such
+    /// as by a [`VoltageLimiter`](crate::voltage_limit::VoltageLimiter).
+    ///
+    /// `excess` is the amount by which the output of the last update exceeds
+    /// the value that was actually applied. This has no effect with
+    /// [`AntiWindup::None`] or [`AntiWindup::IntegratorLimit`].
+    pub fn track_saturation(&mut self, excess: T, dt: T) {
+        self.i.track_saturation(excess, dt);
     }
 }
 
-/// A floating-point PID controller.
+/// A PID controller.
 ///
 /// Uses the derivative-on-measurement technique to avoid derivative kicks on
 /// setpoint changes.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct PIDController {
-    p: ProportionalComponent,
-    i: IntegralComponent,
-    d: DerivativeComponent,
+pub struct PIDController<T = f32> {
+    p: ProportionalComponent<T>,
+    i: IntegralComponent<T>,
+    d: DerivativeComponent<T>,
+    limits: OutputLimits<T>,
 }
 
-impl PIDController {
+impl<T: Number> PIDController<T> {
     /// Create a new controller with the given gains.
-    pub const fn new(k_p: f32, k_i: f32, k_d: f32) -> Self {
+    pub const fn new(k_p: T, k_i: T, k_d: T) -> Self {
         let p = ProportionalComponent { gain: k_p };
 
         let i = IntegralComponent {
             gain: k_i,
-            integral: 0.0,
+            integral: T::ZERO,
+            step: T::ZERO,
+            anti_windup: AntiWindup::None,
         };
 
         let d = DerivativeComponent {
             gain: k_d,
             last_measurement: None,
+            filter: DerivativeFilter::None,
+            time_constant: T::ZERO,
+            stages: [T::ZERO; 2],
         };
 
-        Self { p, i, d }
+        Self {
+            p,
+            i,
+            d,
+            limits: OutputLimits::UNBOUNDED,
+        }
     }
 
     /// Update the controller, returning the new output value.
-    pub fn update(&mut self, setpoint: f32, measurement: f32, dt: f32) -> f32 {
+    pub fn update(&mut self, setpoint: T, measurement: T, dt: T) -> T {
         let error = setpoint - measurement;
 
         let p = self.p.update(error);
-        let i = self.i.update(error, dt);
         let d = self.d.update(measurement, dt);
+        let i = self.i.update(error, dt, p + d, &self.limits);
+
+        self.limits.clamp(p + i + d)
+    }
+
+    /// Apply the anti-windup strategy to saturation after the controller, such
+    /// as by a [`VoltageLimiter`](crate::voltage_limit::VoltageLimiter).
+    ///
+    /// `excess` is the amount by which the output of the last update exceeds
+    /// the value that was actually applied. This has no effect with
+    /// [`AntiWindup::None`] or [`AntiWindup::IntegratorLimit`].
+    pub fn track_saturation(&mut self, excess: T, dt: T) {
+        self.i.track_saturation(excess, dt);
+    }
+}
+
+/// Implement the builders that validate their arguments, which are `const`
+/// for the float types.
+macro_rules! builders {
+    ([$($generics:tt)*] $ty:ty $(, $const:tt)?) => {
+        impl<$($generics)*> PIController<$ty> {
+            /// Limit the output of the controller to the given range.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `min` is greater than `max`.
+            pub $($const)? fn with_output_limits(mut self, min: $ty, max: $ty) -> Self {
+                assert!(min <= max, "min must not be greater than max");
+
+                self.limits = OutputLimits { min, max };
+                self
+            }
+
+            /// Use the given anti-windup strategy when the output saturates.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `min` is greater than `max` for
+            /// [`AntiWindup::IntegratorLimit`].
+            pub $($const)? fn with_anti_windup(mut self, anti_windup: AntiWindup<$ty>) -> Self {
+                if let AntiWindup::IntegratorLimit { min, max } = anti_windup {
+                    assert!(min <= max, "min must not be greater than max");
+                }
+
+                self.i.anti_windup = anti_windup;
+                self
+            }
+        }
+
+        impl<$($generics)*> PIDController<$ty> {
+            /// Limit the output of the controller to the given range.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `min` is greater than `max`.
+            pub $($const)? fn with_output_limits(mut self, min: $ty, max: $ty) -> Self {
+                assert!(min <= max, "min must not be greater than max");
+
+                self.limits = OutputLimits { min, max };
+                self
+            }
+
+            /// Use the given anti-windup strategy when the output saturates.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `min` is greater than `max` for
+            /// [`AntiWindup::IntegratorLimit`].
+            pub $($const)? fn with_anti_windup(mut self, anti_windup: AntiWindup<$ty>) -> Self {
+                if let AntiWindup::IntegratorLimit { min, max } = anti_windup {
+                    assert!(min <= max, "min must not be greater than max");
+                }
+
+                self.i.anti_windup = anti_windup;
+                self
+            }
+
+            /// Low-pass filter the derivative term with the given filter time
+            /// constant, in seconds.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `time_constant` is negative.
+            pub $($const)? fn with_derivative_filter(
+                mut self,
+                filter: DerivativeFilter,
+                time_constant: $ty,
+            ) -> Self {
+                assert!(
+                    time_constant >= <$ty as Number>::ZERO,
+                    "time constant must not be negative"
+                );
+
+                self.d.filter = filter;
+                self.d.time_constant = time_constant;
+                self
+            }
+
+            /// Low-pass filter the derivative term, with the filter time constant
+            /// given as a fraction of the derivative time `k_d / k_p`.
+            ///
+            /// The resulting time constant is `k_d / (k_p * n)`, where `n` is
+            /// typically between 2 and 20.
+            ///
+            /// # Panics
+            ///
+            /// Panics if `n` or `k_p` is not positive.
+            pub $($const)? fn with_derivative_filter_n(
+                self,
+                filter: DerivativeFilter,
+                n: $ty,
+            ) -> Self {
+                assert!(n > <$ty as Number>::ZERO, "n must be positive");
+                assert!(self.p.gain > <$ty as Number>::ZERO, "k_p must be positive");
 
-        p + i + d
+                let time_constant = self.d.gain / (self.p.gain * n);
+                self.with_derivative_filter(filter, time_constant)
+            }
+        }
+    };
+}
+
+builders!([] f32, const);
+builders!([] f64, const);
+builders!([T: Fixed] T);
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct OutputLimits<T> {
+    min: T,
+    max: T,
+}
+
+impl<T: Number> OutputLimits<T> {
+    const UNBOUNDED: Self = Self {
+        min: T::MIN,
+        max: T::MAX,
+    };
+
+    fn clamp(&self, value: T) -> T {
+        num::clamp(value, self.min, self.max)
     }
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-struct ProportionalComponent {
-    gain: f32,
+struct ProportionalComponent<T> {
+    gain: T,
 }
 
-impl ProportionalComponent {
-    fn update(&mut self, error: f32) -> f32 {
+impl<T: Number> ProportionalComponent<T> {
+    fn update(&mut self, error: T) -> T {
         self.gain * error
     }
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-struct IntegralComponent {
-    gain: f32,
-    integral: f32,
+struct IntegralComponent<T> {
+    gain: T,
+    integral: T,
+    /// Change in the integral term during the last update
+    step: T,
+    anti_windup: AntiWindup<T>,
 }
 
-impl IntegralComponent {
-    fn update(&mut self, error: f32, dt: f32) -> f32 {
-        self.integral += self.gain * error * dt;
+impl<T: Number> IntegralComponent<T> {
+    /// Integrate the error, where `rest` is the sum of the other terms making
+    /// up the controller output.
+    fn update(&mut self, error: T, dt: T, rest: T, limits: &OutputLimits<T>) -> T {
+        let previous = self.integral;
+        self.integral = self.integral + self.gain * error * dt;
+
+        match self.anti_windup {
+            AntiWindup::None => {}
+            AntiWindup::Clamping => {
+                let output = rest + self.integral;
+                let excess = output - limits.clamp(output);
+
+                // Only hold the integral if integrating would push the output
+                // further into saturation
+                if excess * self.gain * error > T::ZERO {
+                    self.integral = previous;
+                }
+            }
+            AntiWindup::BackCalculation { tracking_gain } => {
+                let output = rest + self.integral;
+                let excess = output - limits.clamp(output);
+
+                self.integral = self.integral - tracking_gain * excess * dt;
+            }
+            AntiWindup::IntegratorLimit { min, max } => {
+                self.integral = num::clamp(self.integral, min, max);
+            }
+        }
+
+        self.step = self.integral - previous;
         self.integral
     }
+
+    /// Apply the anti-windup strategy to saturation outside the controller.
+    fn track_saturation(&mut self, excess: T, dt: T) {
+        match self.anti_windup {
+            AntiWindup::None | AntiWindup::IntegratorLimit { .. } => {}
+            AntiWindup::Clamping => {
+                // Undo the last step if it pushed the output further into
+                // saturation
+                if excess * self.step > T::ZERO {
+                    self.integral = self.integral - self.step;
+                    self.step = T::ZERO;
+                }
+            }
+            AntiWindup::BackCalculation { tracking_gain } => {
+                self.integral = self.integral - tracking_gain * excess * dt;
+            }
+        }
+    }
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-struct DerivativeComponent {
-    gain: f32,
-    last_measurement: Option<f32>,
+struct DerivativeComponent<T> {
+    gain: T,
+    last_measurement: Option<T>,
+    filter: DerivativeFilter,
+    time_constant: T,
+    stages: [T; 2],
 }
 
-impl DerivativeComponent {
-    fn update(&mut self, measurement: f32, dt: f32) -> f32 {
-        let derivative = self
-            .last_measurement
-            .map(|last| (measurement - last) / dt)
-            .unwrap_or_default();
+impl<T: Number> DerivativeComponent<T> {
+    fn update(&mut self, measurement: T, dt: T) -> T {
+        let Some(last) = self.last_measurement.replace(measurement) else {
+            return T::ZERO;
+        };
+        let difference = measurement - last;
 
-        self.last_measurement = Some(measurement);
+        // Backward Euler discretisation of 1 / (time_constant * s + 1), with
+        // the first stage absorbing the division by dt of the raw derivative
+        let tau = self.time_constant;
+        let derivative = match self.filter {
+            DerivativeFilter::None => difference / dt,
+            DerivativeFilter::FirstOrder => {
+                self.stages[0] = (tau * self.stages[0] + difference) / (tau + dt);
+                self.stages[0]
+            }
+            DerivativeFilter::SecondOrder => {
+                self.stages[0] = (tau * self.stages[0] + difference) / (tau + dt);
+                self.stages[1] = (tau * self.stages[1] + dt * self.stages[0]) / (tau + dt);
+                self.stages[1]
+            }
+        };
 
         self.gain * derivative
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.001;
+
+    /// First-order plant with a 50ms time constant.
+    fn plant(output: f32, measurement: f32) -> f32 {
+        measurement + (output - measurement) * DT / 0.05
+    }
+
+    /// Drive a first-order plant towards an unreachable setpoint until the
+    /// controller saturates, then lower the setpoint and count how many steps
+    /// it takes for the output to leave saturation.
+    fn steps_to_recover(mut update: impl FnMut(f32, f32) -> f32) -> usize {
+        let mut measurement = 0.0;
+
+        for _ in 0..2000 {
+            let output = update(2.0, measurement);
+            measurement = plant(output, measurement);
+        }
+
+        (0..10_000)
+            .position(|_| {
+                let output = update(0.5, measurement);
+                measurement = plant(output, measurement);
+                output < 1.0
+            })
+            .unwrap_or(usize::MAX)
+    }
+
+    fn pi(anti_windup: AntiWindup) -> PIController {
+        PIController::<f32>::new(0.5, 20.0)
+            .with_output_limits(-1.0, 1.0)
+            .with_anti_windup(anti_windup)
+    }
+
+    #[test]
+    fn output_limits() {
+        let mut controller = PIController::<f32>::new(10.0, 0.0).with_output_limits(-1.0, 2.0);
+
+        assert_eq!(controller.update(1.0, 0.0, DT), 2.0);
+        assert_eq!(controller.update(-1.0, 0.0, DT), -1.0);
+        assert_eq!(controller.update(0.05, 0.0, DT), 0.5);
+    }
+
+    #[test]
+    fn pi_windup_without_anti_windup() {
+        let mut controller = pi(AntiWindup::None);
+
+        assert!(steps_to_recover(|s, m| controller.update(s, m, DT)) > 1000);
+    }
+
+    #[test]
+    fn pi_clamping_recovers() {
+        let mut controller = pi(AntiWindup::Clamping);
+
+        assert!(steps_to_recover(|s, m| controller.update(s, m, DT)) < 10);
+    }
+
+    #[test]
+    fn pi_back_calculation_recovers() {
+        let mut controller = pi(AntiWindup::BackCalculation {
+            tracking_gain: 40.0,
+        });
+
+        assert!(steps_to_recover(|s, m| controller.update(s, m, DT)) < 10);
+    }
+
+    #[test]
+    fn pi_tracks_external_saturation() {
+        for anti_windup in [
+            AntiWindup::Clamping,
+            AntiWindup::BackCalculation {
+                tracking_gain: 40.0,
+            },
+        ] {
+            let mut controller = PIController::<f32>::new(0.5, 20.0).with_anti_windup(anti_windup);
+
+            let steps = steps_to_recover(|s, m| {
+                let output = controller.update(s, m, DT);
+                let applied = output.clamp(-1.0, 1.0);
+                controller.track_saturation(output - applied, DT);
+                applied
+            });
+            assert!(steps < 10, "{anti_windup:?}");
+        }
+    }
+
+    #[test]
+    fn pi_integrator_limit_recovers() {
+        let mut controller = pi(AntiWindup::IntegratorLimit {
+            min: -1.0,
+            max: 1.0,
+        });
+
+        assert!(steps_to_recover(|s, m| controller.update(s, m, DT)) < 10);
+    }
+
+    #[test]
+    fn pid_clamping_recovers() {
+        let mut controller = PIDController::<f32>::new(0.5, 20.0, 0.001)
+            .with_output_limits(-1.0, 1.0)
+            .with_anti_windup(AntiWindup::Clamping);
+
+        assert!(steps_to_recover(|s, m| controller.update(s, m, DT)) < 10);
+    }
+
+    /// Feed a quantised ramp with a slope of 1 into a derivative-only
+    /// controller, returning the worst error in the derivative once settled.
+    fn derivative_error(filter: DerivativeFilter, dt: impl Fn(usize) -> f32) -> f32 {
+        let mut controller =
+            PIDController::<f32>::new(0.0, 0.0, 1.0).with_derivative_filter(filter, 0.02);
+        let mut time = 0.0;
+
+        (0..2000)
+            .map(|step| {
+                let dt = dt(step);
+                time += dt;
+                let measurement = libm::roundf(time * 100.0) / 100.0;
+                controller.update(0.0, measurement, dt)
+            })
+            .skip(1000)
+            .map(|derivative| (derivative.abs() - 1.0).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn derivative_filter_attenuates_quantisation() {
+        let raw = derivative_error(DerivativeFilter::None, |_| DT);
+        let first = derivative_error(DerivativeFilter::FirstOrder, |_| DT);
+        let second = derivative_error(DerivativeFilter::SecondOrder, |_| DT);
+
+        assert!(raw > 5.0);
+        assert!(first < 0.5);
+        assert!(second < first);
+    }
+
+    #[test]
+    fn derivative_filter_stable_with_varying_dt() {
+        let dt = |step| if step % 7 == 0 { 0.05 } else { 0.0002 };
+
+        assert!(derivative_error(DerivativeFilter::FirstOrder, dt) < 1.0);
+        assert!(derivative_error(DerivativeFilter::SecondOrder, dt) < 1.0);
+    }
+
+    #[test]
+    fn derivative_filter_n() {
+        let mut by_n = PIDController::<f32>::new(2.0, 0.0, 0.1)
+            .with_derivative_filter_n(DerivativeFilter::FirstOrder, 5.0);
+        let mut by_time_constant = PIDController::<f32>::new(2.0, 0.0, 0.1)
+            .with_derivative_filter(DerivativeFilter::FirstOrder, 0.01);
+
+        for measurement in [0.0, 1.0, 1.0, 3.0, 2.0] {
+            let a = by_n.update(0.0, measurement, DT);
+            let b = by_time_constant.update(0.0, measurement, DT);
+            assert!((a - b).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn pi_settles_after_saturation() {
+        let mut controller = pi(AntiWindup::BackCalculation {
+            tracking_gain: 40.0,
+        });
+        let mut measurement = 0.0;
+
+        for setpoint in [2.0, 0.5] {
+            for _ in 0..2000 {
+                let output = controller.update(setpoint, measurement, DT);
+                measurement = plant(output, measurement);
+            }
+        }
+
+        assert!((measurement - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn f64_matches_f32() {
+        let mut single = PIDController::<f32>::new(2.0, 20.0, 0.1)
+            .with_output_limits(-1.0, 1.0)
+            .with_anti_windup(AntiWindup::Clamping)
+            .with_derivative_filter(DerivativeFilter::SecondOrder, 0.01);
+        let mut double = PIDController::<f64>::new(2.0, 20.0, 0.1)
+            .with_output_limits(-1.0, 1.0)
+            .with_anti_windup(AntiWindup::Clamping)
+            .with_derivative_filter(DerivativeFilter::SecondOrder, 0.01);
+        let mut measurement = 0.0;
+
+        for _ in 0..1000 {
+            let output = single.update(0.5, measurement, DT);
+            let expected = double.update(0.5, measurement as f64, DT as f64);
+            assert!((output as f64 - expected).abs() < 1e-5);
+
+            measurement = plant(output, measurement);
+        }
+    }
+
+    #[test]
+    fn const_builders() {
+        const SINGLE: PIDController = PIDController::<f32>::new(2.0, 20.0, 0.1)
+            .with_output_limits(-1.0, 1.0)
+            .with_derivative_filter_n(DerivativeFilter::FirstOrder, 5.0);
+
+        const DOUBLE: PIController<f64> =
+            PIController::<f64>::new(2.0, 20.0).with_output_limits(-1.0, 1.0);
+
+        let mut double = DOUBLE;
+        assert_eq!(double.update(2.0, 0.0, DT as f64), 1.0);
+
+        let mut controller = SINGLE;
+        assert_eq!(controller.update(2.0, 0.0, DT), 1.0);
+        assert_eq!(controller.update(-2.0, 0.0, DT), -1.0);
+    }
+
+    #[test]
+    fn fixed_point_builders() {
+        use crate::fixed::Q15;
+
+        let mut controller = PIController::new(Q15::from_f32(0.5), Q15::ZERO)
+            .with_output_limits(Q15::from_f32(-0.25), Q15::from_f32(0.25));
+        assert_eq!(
+            controller.update(Q15::MAX, Q15::ZERO, Q15::ZERO),
+            Q15::from_f32(0.25)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "min must not be greater than max")]
+    fn inverted_integrator_limit() {
+        let _ = PIController::<f32>::new(0.5, 20.0).with_anti_windup(AntiWindup::IntegratorLimit {
+            min: 1.0,
+            max: -1.0,
+        });
+    }
+}
@@ -0,0 +1,189 @@
+//! Phase-locked loop for tracking an angle and its derivatives.
+//!
+//! Useful for smoothing noisy angle sources such as quantised encoders, the
+//! output of observers, or interpolated Hall sensors. The tracked angle can
+//! be used to provide the sine and cosine arguments of
+//! [`park`](crate::park_clarke::park) and
+//! [`inverse_park`](crate::park_clarke::inverse_park).
+
+/// A phase-locked loop tracking an angle, its speed and optionally its
+/// acceleration.
+///
+/// The loop is a third-order tracker with the characteristic polynomial
+/// `s³ + k_1 s² + k_2 s + k_3`. Setting `k_3` to zero gives a second-order
+/// tracker which follows a constant speed without error, while a non-zero
+/// `k_3` additionally follows a constant acceleration without error.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PhaseLockedLoop {
+    k_1: f32,
+    k_2: f32,
+    k_3: f32,
+    angle: f32,
+    speed: f32,
+    acceleration: f32,
+}
+
+impl PhaseLockedLoop {
+    /// Create a new loop with the given gains.
+    pub const fn new(k_1: f32, k_2: f32, k_3: f32) -> Self {
+        Self {
+            k_1,
+            k_2,
+            k_3,
+            angle: 0.0,
+            speed: 0.0,
+            acceleration: 0.0,
+        }
+    }
+
+    /// Create a critically damped second-order loop with the given bandwidth
+    /// in radians per second.
+    pub const fn from_bandwidth(bandwidth: f32) -> Self {
+        Self::new(2.0 * bandwidth, bandwidth * bandwidth, 0.0)
+    }
+
+    /// Create a critically damped third-order loop, which also estimates the
+    /// acceleration, with the given bandwidth in radians per second.
+    pub const fn from_bandwidth_with_acceleration(bandwidth: f32) -> Self {
+        Self::new(
+            3.0 * bandwidth,
+            3.0 * bandwidth * bandwidth,
+            bandwidth * bandwidth * bandwidth,
+        )
+    }
+
+    /// Update the loop with a measured angle in radians.
+    pub fn update_angle(&mut self, angle: f32, dt: f32) {
+        self.predict(dt);
+        let error = crate::wrap_angle_difference(angle - self.angle);
+        self.correct(error, dt);
+    }
+
+    /// Update the loop with the sine and cosine of the measured angle.
+    ///
+    /// The pair does not need to be normalised, however the loop gain scales
+    /// with its magnitude.
+    pub fn update_sin_cos(&mut self, sin_angle: f32, cos_angle: f32, dt: f32) {
+        self.predict(dt);
+        let (sin_estimate, cos_estimate) = libm::sincosf(self.angle);
+        let error = sin_angle * cos_estimate - cos_angle * sin_estimate;
+        self.correct(error, dt);
+    }
+
+    /// Advance the estimate to the time of the new measurement.
+    fn predict(&mut self, dt: f32) {
+        self.angle = crate::wrap_angle(self.angle + self.speed * dt);
+        self.speed += self.acceleration * dt;
+    }
+
+    /// Correct the estimate using the error between the new measurement and
+    /// the predicted angle.
+    fn correct(&mut self, error: f32, dt: f32) {
+        self.angle = crate::wrap_angle(self.angle + self.k_1 * error * dt);
+        self.speed += self.k_2 * error * dt;
+        self.acceleration += self.k_3 * error * dt;
+    }
+
+    /// Tracked angle in radians, between 0 and 2π.
+    pub fn angle(&self) -> f32 {
+        self.angle
+    }
+
+    /// Tracked speed in radians per second.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Tracked acceleration in radians per second squared.
+    ///
+    /// This is always zero for second-order loops.
+    pub fn acceleration(&self) -> f32 {
+        self.acceleration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.0001;
+
+    /// Track an angle following the given trajectory for one second,
+    /// quantised to the given resolution.
+    fn track(
+        mut pll: PhaseLockedLoop,
+        resolution: f32,
+        trajectory: impl Fn(f32) -> f32,
+    ) -> (PhaseLockedLoop, f32) {
+        let mut angle = 0.0;
+
+        for step in 1..=10_000 {
+            angle = trajectory(step as f32 * DT);
+            let measured = libm::floorf(angle / resolution) * resolution;
+            pll.update_angle(crate::wrap_angle(measured), DT);
+        }
+
+        (pll, angle)
+    }
+
+    #[test]
+    fn tracks_quantised_constant_speed() {
+        let resolution = core::f32::consts::TAU / 4096.0;
+        let (pll, angle) = track(PhaseLockedLoop::from_bandwidth(100.0), resolution, |t| {
+            300.0 * t
+        });
+
+        let error = crate::wrap_angle_difference(pll.angle() - angle);
+        assert!(error.abs() < 2.0 * resolution, "{error}");
+        assert!((pll.speed() - 300.0).abs() < 1.0, "{}", pll.speed());
+    }
+
+    #[test]
+    fn tracks_negative_speed() {
+        let (pll, angle) = track(PhaseLockedLoop::from_bandwidth(100.0), 0.001, |t| {
+            -300.0 * t
+        });
+
+        assert!(crate::wrap_angle_difference(pll.angle() - angle).abs() < 0.01);
+        assert!((pll.speed() + 300.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn tracks_constant_acceleration() {
+        let trajectory = |t: f32| 200.0 * t * t;
+
+        let (second_order, angle) =
+            track(PhaseLockedLoop::from_bandwidth(50.0), 0.0001, trajectory);
+        let (third_order, _) = track(
+            PhaseLockedLoop::from_bandwidth_with_acceleration(50.0),
+            0.0001,
+            trajectory,
+        );
+
+        // A second-order loop lags by acceleration / bandwidth² under
+        // constant acceleration
+        let lag = crate::wrap_angle_difference(angle - second_order.angle());
+        assert!((lag - 400.0 / 2500.0).abs() < 0.01, "{lag}");
+
+        let error = crate::wrap_angle_difference(third_order.angle() - angle);
+        assert!(error.abs() < 0.001, "{error}");
+        assert!((third_order.speed() - 400.0).abs() < 0.5);
+        assert!((third_order.acceleration() - 400.0).abs() < 5.0);
+        assert_eq!(second_order.acceleration(), 0.0);
+    }
+
+    #[test]
+    fn tracks_sin_cos() {
+        let mut pll = PhaseLockedLoop::from_bandwidth(200.0);
+
+        for step in 1..=10_000 {
+            let (sin_angle, cos_angle) = libm::sincosf(500.0 * step as f32 * DT);
+            pll.update_sin_cos(sin_angle, cos_angle, DT);
+        }
+
+        let error = crate::wrap_angle_difference(pll.angle() - 500.0 * 10_000.0 * DT);
+        assert!(error.abs() < 0.001, "{error}");
+        assert!((pll.speed() - 500.0).abs() < 0.1);
+    }
+}
@@ -4,11 +4,20 @@
 //! The resulting waveforms of the PWM generation methods are shown below.
 //! ![PWM Methods](https://raw.githubusercontent.com/phycrax/foc/main/docs/pwm_methods.png)
 
-use crate::{park_clarke::TwoPhaseReferenceFrame, SQRT_3};
+use core::marker::PhantomData;
+
+use crate::{
+    num::Number, overmodulation::Overmodulation, park_clarke::TwoPhaseReferenceFrame,
+    FRAC_1_SQRT_3, SQRT_3,
+};
 
 /// Trait to generalize converting a value from a two-phase stationary orthogonal
 /// reference frame to a value suitable to be used for PWM generation.
 pub trait Modulation {
+    /// Peak phase voltage, as a fraction of the DC bus voltage, produced by an
+    /// input of unit magnitude.
+    const VOLTAGE_SCALE: f32 = 0.5;
+
     /// Generate PWM values based on a specific implementation.
     ///
     /// Returns a value between -1 and 1 for each channel.
@@ -20,6 +29,48 @@ pub trait Modulation {
         Self::modulate(value)
             .map(|val| (((val + 1.0) * (max as f32 + 1.0)) / 2.0).clamp(0.0, max as f32) as u16)
     }
+
+    /// Generate PWM values for a voltage in volts, given the measured DC bus
+    /// voltage.
+    ///
+    /// The voltage is normalised using [`Self::VOLTAGE_SCALE`]. Measuring the
+    /// bus voltage every PWM period compensates for ripple on the bus.
+    ///
+    /// Returns a value between -1 and 1 for each channel.
+    fn modulate_voltage(voltage: TwoPhaseReferenceFrame, bus_voltage: f32) -> [f32; 3] {
+        Self::modulate(normalise_voltage(voltage, bus_voltage, Self::VOLTAGE_SCALE))
+    }
+
+    /// Module a voltage in volts, given the measured DC bus voltage, returning
+    /// the result as a value between 0 and the specified maximum value
+    /// inclusive.
+    fn voltage_as_compare_value(
+        voltage: TwoPhaseReferenceFrame,
+        bus_voltage: f32,
+        max: u16,
+    ) -> [u16; 3] {
+        Self::as_compare_value(
+            normalise_voltage(voltage, bus_voltage, Self::VOLTAGE_SCALE),
+            max,
+        )
+    }
+}
+
+/// Normalise a voltage in volts to the input range of a modulation method
+/// with the given voltage scale, such as [`Modulation::VOLTAGE_SCALE`].
+///
+/// A bus voltage that is not positive results in a zero output, rather than
+/// dividing by zero.
+pub fn normalise_voltage(
+    voltage: TwoPhaseReferenceFrame,
+    bus_voltage: f32,
+    voltage_scale: f32,
+) -> TwoPhaseReferenceFrame {
+    if bus_voltage <= 0.0 {
+        return TwoPhaseReferenceFrame::default();
+    }
+
+    voltage * (1.0 / (voltage_scale * bus_voltage))
 }
 
 /// Generate PWM values based on a space-vector method.
@@ -28,54 +79,66 @@ pub trait Modulation {
 /// PWM while having better current ripple than the other methods. However, it
 /// comes at the expense of a more complex computation.
 ///
+/// Inputs with a magnitude above 1 leave the inscribed circle of the voltage
+/// hexagon, and should be limited with
+/// [`Overmodulation`].
+///
 /// Returns a value between -1 and 1 for each channel.
 pub struct SpaceVector;
 
 impl Modulation for SpaceVector {
+    const VOLTAGE_SCALE: f32 = FRAC_1_SQRT_3;
+
     fn modulate(value: TwoPhaseReferenceFrame) -> [f32; 3] {
-        // Convert alpha/beta to x/y/z
-        let sqrt_3_alpha = SQRT_3 * value.alpha;
-        let beta = value.beta;
-        let x = beta;
-        let y = (beta + sqrt_3_alpha) / 2.0;
-        let z = (beta - sqrt_3_alpha) / 2.0;
-
-        // Calculate which sector the value falls in
-        let sector: u8 = match (
-            x.is_sign_positive(),
-            y.is_sign_positive(),
-            z.is_sign_positive(),
-        ) {
-            (true, true, false) => 1,
-            (_, true, true) => 2,
-            (true, false, true) => 3,
-            (false, false, true) => 4,
-            (_, false, false) => 5,
-            (false, true, false) => 6,
-        };
-
-        // Map a,b,c values to three phase
-        let (ta, tb, tc);
-        match sector {
-            1 | 4 => {
-                ta = x - z;
-                tb = x + z;
-                tc = -x + z;
-            }
-            2 | 5 => {
-                ta = y - z;
-                tb = y + z;
-                tc = -y - z;
-            }
-            3 | 6 => {
-                ta = y - x;
-                tb = -y + x;
-                tc = -y - x;
-            }
-            _ => unreachable!("invalid sector"),
-        }
+        space_vector(value)
+    }
+}
+
+/// Space-vector modulation for any [`Number`] type, as used by
+/// [`SpaceVector`].
+///
+/// Returns a value between -1 and 1 for each channel, for inputs within the
+/// inscribed circle of the hexagon.
+pub fn space_vector<T: Number>(value: TwoPhaseReferenceFrame<T>) -> [T; 3] {
+    let (x, y, z) = xyz(&value);
+
+    // Map a,b,c values to three phase
+    match sector_from_xyz(x, y, z) {
+        1 | 4 => [x - z, x + z, z - x],
+        2 | 5 => [y - z, y + z, -y - z],
+        3 | 6 => [y - x, x - y, -y - x],
+        _ => unreachable!("invalid sector"),
+    }
+}
+
+/// Sector of the voltage hexagon that a space-vector input falls in, numbered
+/// from 1 to 6 for each 60° starting from the alpha axis.
+pub fn sector<T: Number>(value: &TwoPhaseReferenceFrame<T>) -> u8 {
+    let (x, y, z) = xyz(value);
+
+    sector_from_xyz(x, y, z)
+}
+
+/// Convert alpha/beta to x/y/z
+fn xyz<T: Number>(value: &TwoPhaseReferenceFrame<T>) -> (T, T, T) {
+    let half_sqrt_3_alpha = T::FRAC_SQRT_3_2 * value.alpha;
+    let half_beta = T::HALF * value.beta;
+    let x = value.beta;
+    let y = half_beta + half_sqrt_3_alpha;
+    let z = half_beta - half_sqrt_3_alpha;
 
-        [ta, tb, tc]
+    (x, y, z)
+}
+
+/// Calculate which sector the value falls in
+fn sector_from_xyz<T: Number>(x: T, y: T, z: T) -> u8 {
+    match (x >= T::ZERO, y >= T::ZERO, z >= T::ZERO) {
+        (true, true, false) => 1,
+        (_, true, true) => 2,
+        (true, false, true) => 3,
+        (false, false, true) => 4,
+        (_, false, false) => 5,
+        (false, true, false) => 6,
     }
 }
 
@@ -95,6 +158,86 @@ impl Modulation for Sinusoidal {
     }
 }
 
+/// A common-mode signal added to all three phases by
+/// [`ZeroSequenceInjection`].
+///
+/// The common mode does not affect the line-to-line voltages, but changes the
+/// peak phase voltage and so the range of inputs that can be produced without
+/// clamping.
+pub trait ZeroSequence {
+    /// Calculate the common-mode signal to add, given the phase voltages from
+    /// [`inverse_clarke`](crate::park_clarke::inverse_clarke).
+    fn zero_sequence(phases: &[f32; 3]) -> f32;
+}
+
+/// Generate PWM values by adding a common-mode signal, chosen by `Z`, to the
+/// sinusoidal phase voltages.
+///
+/// The input is scaled as for [`Sinusoidal`], however inputs with a magnitude
+/// above 1 can be produced without clamping, depending on `Z`.
+///
+/// Returns a value between -1 and 1 for each channel.
+pub struct ZeroSequenceInjection<Z: ZeroSequence>(PhantomData<Z>);
+
+impl<Z: ZeroSequence> Modulation for ZeroSequenceInjection<Z> {
+    fn modulate(value: TwoPhaseReferenceFrame) -> [f32; 3] {
+        let voltages = crate::park_clarke::inverse_clarke(value);
+        let phases = [voltages.a, voltages.b, voltages.c];
+        let common = Z::zero_sequence(&phases);
+
+        phases.map(|phase| phase + common)
+    }
+}
+
+/// Centre the highest and lowest phase voltages between the rails.
+///
+/// This gives the same line-to-line voltages as [`SpaceVector`], and inputs
+/// with a magnitude up to 2/√3 can be produced without clamping.
+pub struct MinMax;
+
+impl ZeroSequence for MinMax {
+    fn zero_sequence(phases: &[f32; 3]) -> f32 {
+        let max = phases[0].max(phases[1]).max(phases[2]);
+        let min = phases[0].min(phases[1]).min(phases[2]);
+
+        -(max + min) / 2.0
+    }
+}
+
+/// Inject a third harmonic with an amplitude of `1 / DIVISOR` of the
+/// fundamental.
+///
+/// An amplitude of 1/6 allows inputs with a magnitude up to 2/√3 to be
+/// produced without clamping, while 1/4 minimises the harmonic content of the
+/// current ripple, allowing inputs up to 1.12. Using a `DIVISOR` of zero fails
+/// to compile.
+pub struct ThirdHarmonic<const DIVISOR: u8 = 6>;
+
+impl<const DIVISOR: u8> ZeroSequence for ThirdHarmonic<DIVISOR> {
+    fn zero_sequence(phases: &[f32; 3]) -> f32 {
+        const { assert!(DIVISOR > 0, "divisor must not be zero") };
+
+        let [a, b, c] = *phases;
+        let magnitude_squared = a * a + (b - c) * (b - c) / 3.0;
+        if magnitude_squared == 0.0 {
+            return 0.0;
+        }
+
+        // m cos(3θ) = 4a³/m² - 3a, where a = m cos(θ)
+        let third_harmonic = 4.0 * a * a * a / magnitude_squared - 3.0 * a;
+        -third_harmonic / DIVISOR as f32
+    }
+}
+
+/// Generate PWM values based on a sinusoidal waveform with an injected third
+/// harmonic, using an amplitude of `1 / DIVISOR` of the fundamental.
+///
+/// See [`ThirdHarmonic`] for the choice of amplitude.
+///
+/// Returns a value between -1 and 1 for each channel.
+pub type ThirdHarmonicInjection<const DIVISOR: u8 = 6> =
+    ZeroSequenceInjection<ThirdHarmonic<DIVISOR>>;
+
 /// Generate PWM values based on a trapezoidal wave.
 ///
 /// Note that for this method to work properly, when the output is 0 the
@@ -131,3 +274,561 @@ impl Modulation for Square {
         ]
     }
 }
+
+/// The result of a single update of a [`Modulator`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ModulatorOutput {
+    /// Duty cycle of each phase, between 0 and 1 inclusive
+    pub duty: [f32; 3],
+    /// Sector of the voltage hexagon containing the applied vector, from 1 to
+    /// 6, as given by [`sector`]
+    pub sector: u8,
+    /// Whether the requested vector could not be produced exactly, because it
+    /// was limited or the duty cycles were clamped or rounded
+    pub saturated: bool,
+    /// Requested peak phase voltage relative to half the DC bus voltage
+    pub modulation_index: f32,
+}
+
+impl ModulatorOutput {
+    /// The duty cycles as compare values between 0 and the specified maximum
+    /// value inclusive.
+    pub fn as_compare_value(&self, max: u16) -> [u16; 3] {
+        self.duty
+            .map(|duty| (duty * (max as f32 + 1.0)).clamp(0.0, max as f32) as u16)
+    }
+}
+
+/// A modulator that may hold configuration and state between updates, and
+/// reports details of each update.
+///
+/// Every [`Modulation`] method is also a [`Modulator`].
+pub trait Modulator {
+    /// Peak phase voltage, as a fraction of the DC bus voltage, produced by an
+    /// input of unit magnitude.
+    ///
+    /// This is [`Modulation::VOLTAGE_SCALE`] for the blanket implementation.
+    fn voltage_scale(&self) -> f32;
+
+    /// Generate duty cycles for a value in the two-phase stationary reference
+    /// frame.
+    fn update(&mut self, value: TwoPhaseReferenceFrame) -> ModulatorOutput;
+
+    /// Generate duty cycles for a voltage in volts in the two-phase stationary
+    /// reference frame, given the measured DC bus voltage.
+    ///
+    /// The voltage is normalised using [`Self::voltage_scale`]. Measuring the
+    /// bus voltage every PWM period compensates for ripple on the bus.
+    fn update_voltage(
+        &mut self,
+        voltage: TwoPhaseReferenceFrame,
+        bus_voltage: f32,
+    ) -> ModulatorOutput {
+        let value = normalise_voltage(voltage, bus_voltage, self.voltage_scale());
+        self.update(value)
+    }
+}
+
+impl<M: Modulation> Modulator for M {
+    fn voltage_scale(&self) -> f32 {
+        M::VOLTAGE_SCALE
+    }
+
+    fn update(&mut self, value: TwoPhaseReferenceFrame) -> ModulatorOutput {
+        let magnitude = libm::hypotf(value.alpha, value.beta);
+        let sector = sector(&value);
+        let outputs = M::modulate(value);
+
+        ModulatorOutput {
+            duty: outputs.map(|output| ((output + 1.0) / 2.0).clamp(0.0, 1.0)),
+            sector,
+            saturated: outputs.iter().any(|output| output.abs() > 1.0),
+            modulation_index: 2.0 * M::VOLTAGE_SCALE * magnitude,
+        }
+    }
+}
+
+/// A configurable space-vector modulator.
+///
+/// Produces the same duty cycles as [`SpaceVector`] within the inscribed
+/// circle of the voltage hexagon, with optional overmodulation and removal of
+/// narrow pulses.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SpaceVectorModulator {
+    overmodulation: Overmodulation,
+    minimum_pulse: f32,
+}
+
+impl SpaceVectorModulator {
+    /// Create a new modulator without overmodulation or a minimum pulse
+    /// width.
+    pub const fn new() -> Self {
+        Self {
+            overmodulation: Overmodulation::None,
+            minimum_pulse: 0.0,
+        }
+    }
+
+    /// Limit inputs outside the inscribed circle of the voltage hexagon using
+    /// the given method.
+    pub const fn with_overmodulation(mut self, overmodulation: Overmodulation) -> Self {
+        self.overmodulation = overmodulation;
+        self
+    }
+
+    /// Round duty cycles within the given fraction of the PWM period of 0 or 1
+    /// to 0 or 1, avoiding pulses too narrow for the switches or to sample
+    /// the current during. The output is saturated when a duty cycle is
+    /// rounded.
+    pub const fn with_minimum_pulse(mut self, minimum_pulse: f32) -> Self {
+        self.minimum_pulse = minimum_pulse;
+        self
+    }
+}
+
+impl Default for SpaceVectorModulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Modulator for SpaceVectorModulator {
+    fn voltage_scale(&self) -> f32 {
+        FRAC_1_SQRT_3
+    }
+
+    fn update(&mut self, value: TwoPhaseReferenceFrame) -> ModulatorOutput {
+        let magnitude = libm::hypotf(value.alpha, value.beta);
+        let limited = self.overmodulation.apply(value);
+        let outputs = space_vector(limited);
+
+        let mut saturated = limited != value;
+        let duty = outputs.map(|output| {
+            let duty = (output + 1.0) / 2.0;
+            saturated |= !(0.0..=1.0).contains(&duty);
+
+            let rounded = if duty < self.minimum_pulse {
+                0.0
+            } else if duty > 1.0 - self.minimum_pulse {
+                1.0
+            } else {
+                duty
+            };
+            saturated |= rounded != duty;
+
+            rounded
+        });
+
+        ModulatorOutput {
+            duty,
+            sector: sector(&limited),
+            saturated,
+            modulation_index: 2.0 * FRAC_1_SQRT_3 * magnitude,
+        }
+    }
+}
+
+/// Phase voltages of a space-vector input, scaled so that the inscribed circle
+/// of the voltage hexagon reaches 1 between phases, with no zero sequence.
+fn phase_references(value: TwoPhaseReferenceFrame) -> [f32; 3] {
+    let voltages = crate::park_clarke::inverse_clarke(value);
+
+    [voltages.a, voltages.b, voltages.c].map(|voltage| voltage * 2.0 * FRAC_1_SQRT_3)
+}
+
+/// Clamp the phase with the highest reference to the positive rail.
+fn clamp_max(references: [f32; 3]) -> [f32; 3] {
+    let max = references[0].max(references[1]).max(references[2]);
+
+    references.map(|reference| reference + 1.0 - max)
+}
+
+/// Clamp the phase with the lowest reference to the negative rail.
+fn clamp_min(references: [f32; 3]) -> [f32; 3] {
+    let min = references[0].min(references[1]).min(references[2]);
+
+    references.map(|reference| reference - 1.0 - min)
+}
+
+/// Clamp the phase with the largest reference magnitude to its rail, after
+/// rotating the input by the given sine and cosine of an angle. The rotation
+/// moves the 60° clamping interval away from the peak of each phase.
+fn clamp_largest(value: TwoPhaseReferenceFrame, sin_shift: f32, cos_shift: f32) -> [f32; 3] {
+    let shifted = phase_references(TwoPhaseReferenceFrame {
+        alpha: cos_shift * value.alpha - sin_shift * value.beta,
+        beta: sin_shift * value.alpha + cos_shift * value.beta,
+    });
+    let max = shifted[0].max(shifted[1]).max(shifted[2]);
+    let min = shifted[0].min(shifted[1]).min(shifted[2]);
+
+    let references = phase_references(value);
+    if max >= -min {
+        clamp_max(references)
+    } else {
+        clamp_min(references)
+    }
+}
+
+/// Generate PWM values based on the DPWM0 discontinuous method.
+///
+/// Each phase is clamped to a rail for the 60° leading up to the peak of its
+/// voltage, which minimises switching losses for loads with a leading power
+/// factor around 0.87. The line-to-line voltages are the same as
+/// [`SpaceVector`].
+///
+/// Returns a value between -1 and 1 for each channel.
+pub struct Dpwm0;
+
+impl Modulation for Dpwm0 {
+    const VOLTAGE_SCALE: f32 = FRAC_1_SQRT_3;
+
+    fn modulate(value: TwoPhaseReferenceFrame) -> [f32; 3] {
+        // Rotate forwards by 30°
+        clamp_largest(value, 0.5, SQRT_3 / 2.0)
+    }
+}
+
+/// Generate PWM values based on the DPWM1 discontinuous method.
+///
+/// Each phase is clamped to a rail for the 60° centred on the peak of its
+/// voltage, which minimises switching losses for loads with a power factor
+/// near unity. The line-to-line voltages are the same as [`SpaceVector`].
+///
+/// Returns a value between -1 and 1 for each channel.
+pub struct Dpwm1;
+
+impl Modulation for Dpwm1 {
+    const VOLTAGE_SCALE: f32 = FRAC_1_SQRT_3;
+
+    fn modulate(value: TwoPhaseReferenceFrame) -> [f32; 3] {
+        clamp_largest(value, 0.0, 1.0)
+    }
+}
+
+/// Generate PWM values based on the DPWM2 discontinuous method.
+///
+/// Each phase is clamped to a rail for the 60° following the peak of its
+/// voltage, which minimises switching losses for loads with a lagging power
+/// factor around 0.87, such as motors. The line-to-line voltages are the same
+/// as [`SpaceVector`].
+///
+/// Returns a value between -1 and 1 for each channel.
+pub struct Dpwm2;
+
+impl Modulation for Dpwm2 {
+    const VOLTAGE_SCALE: f32 = FRAC_1_SQRT_3;
+
+    fn modulate(value: TwoPhaseReferenceFrame) -> [f32; 3] {
+        // Rotate backwards by 30°
+        clamp_largest(value, -0.5, SQRT_3 / 2.0)
+    }
+}
+
+/// Generate PWM values based on the DPWM3 discontinuous method.
+///
+/// Each phase is clamped to a rail for two 30° intervals either side of the
+/// peak of its voltage, between 30° and 60° away from it. The line-to-line
+/// voltages are the same as [`SpaceVector`].
+///
+/// Returns a value between -1 and 1 for each channel.
+pub struct Dpwm3;
+
+impl Modulation for Dpwm3 {
+    const VOLTAGE_SCALE: f32 = FRAC_1_SQRT_3;
+
+    fn modulate(value: TwoPhaseReferenceFrame) -> [f32; 3] {
+        let references = phase_references(value);
+        let max = references[0].max(references[1]).max(references[2]);
+        let min = references[0].min(references[1]).min(references[2]);
+
+        // Clamp the phase with the smaller magnitude of the two extremes
+        if max < -min {
+            clamp_max(references)
+        } else {
+            clamp_min(references)
+        }
+    }
+}
+
+/// Generate PWM values based on the DPWMMIN discontinuous method.
+///
+/// The phase with the lowest voltage is always clamped to the negative rail,
+/// for 120° of each cycle. This suits bootstrapped gate drivers, as the low
+/// side switches conduct for longer. The line-to-line voltages are the same as
+/// [`SpaceVector`].
+///
+/// Returns a value between -1 and 1 for each channel.
+pub struct DpwmMin;
+
+impl Modulation for DpwmMin {
+    const VOLTAGE_SCALE: f32 = FRAC_1_SQRT_3;
+
+    fn modulate(value: TwoPhaseReferenceFrame) -> [f32; 3] {
+        clamp_min(phase_references(value))
+    }
+}
+
+/// Generate PWM values based on the DPWMMAX discontinuous method.
+///
+/// The phase with the highest voltage is always clamped to the positive rail,
+/// for 120° of each cycle. The line-to-line voltages are the same as
+/// [`SpaceVector`].
+///
+/// Returns a value between -1 and 1 for each channel.
+pub struct DpwmMax;
+
+impl Modulation for DpwmMax {
+    const VOLTAGE_SCALE: f32 = FRAC_1_SQRT_3;
+
+    fn modulate(value: TwoPhaseReferenceFrame) -> [f32; 3] {
+        clamp_max(phase_references(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::TAU;
+
+    fn polar(magnitude: f32, angle: f32) -> TwoPhaseReferenceFrame {
+        let (sin_angle, cos_angle) = libm::sincosf(angle);
+        TwoPhaseReferenceFrame {
+            alpha: magnitude * cos_angle,
+            beta: magnitude * sin_angle,
+        }
+    }
+
+    fn line_to_line(outputs: [f32; 3]) -> [f32; 3] {
+        [
+            outputs[0] - outputs[1],
+            outputs[1] - outputs[2],
+            outputs[2] - outputs[0],
+        ]
+    }
+
+    #[track_caller]
+    fn assert_matches_space_vector<M: Modulation>() {
+        assert_eq!(M::VOLTAGE_SCALE, SpaceVector::VOLTAGE_SCALE);
+
+        for magnitude in [0.1, 0.5, 1.0] {
+            for step in 0..360 {
+                let value = polar(magnitude, step as f32 * TAU / 360.0);
+                let outputs = M::modulate(value);
+                let expected = line_to_line(SpaceVector::modulate(value));
+
+                for (output, expected) in line_to_line(outputs).into_iter().zip(expected) {
+                    assert!((output - expected).abs() < 0.0001);
+                }
+
+                // One phase is always clamped to a rail
+                assert!(outputs.iter().all(|output| output.abs() < 1.0001));
+                assert!(outputs.iter().any(|output| output.abs() > 0.9999));
+            }
+        }
+    }
+
+    #[test]
+    fn discontinuous_matches_space_vector() {
+        assert_matches_space_vector::<Dpwm0>();
+        assert_matches_space_vector::<Dpwm1>();
+        assert_matches_space_vector::<Dpwm2>();
+        assert_matches_space_vector::<Dpwm3>();
+        assert_matches_space_vector::<DpwmMin>();
+        assert_matches_space_vector::<DpwmMax>();
+    }
+
+    /// Whether phase A is clamped to the positive rail at the given angle in
+    /// degrees from the peak of its voltage.
+    fn clamped<M: Modulation>(degrees: f32) -> bool {
+        let outputs = M::modulate(polar(0.8, degrees.to_radians()));
+        outputs[0] > 0.9999
+    }
+
+    #[test]
+    fn clamping_intervals() {
+        assert!(clamped::<Dpwm0>(-50.0));
+        assert!(!clamped::<Dpwm0>(10.0));
+
+        assert!(clamped::<Dpwm1>(-25.0));
+        assert!(clamped::<Dpwm1>(25.0));
+        assert!(!clamped::<Dpwm1>(35.0));
+
+        assert!(!clamped::<Dpwm2>(-10.0));
+        assert!(clamped::<Dpwm2>(50.0));
+
+        assert!(!clamped::<Dpwm3>(0.0));
+        assert!(clamped::<Dpwm3>(-45.0));
+        assert!(clamped::<Dpwm3>(45.0));
+
+        assert!(clamped::<DpwmMax>(55.0));
+        assert!(!clamped::<DpwmMin>(0.0));
+    }
+
+    /// Largest output over one revolution of an input of the given magnitude.
+    fn peak<M: Modulation>(magnitude: f32) -> f32 {
+        (0..360)
+            .flat_map(|step| M::modulate(polar(magnitude, step as f32 * TAU / 360.0)))
+            .fold(0.0, |max: f32, output| max.max(output.abs()))
+    }
+
+    #[test]
+    fn min_max_matches_space_vector() {
+        for step in 0..360 {
+            let value = polar(0.9, step as f32 * TAU / 360.0);
+            let expected = SpaceVector::modulate(value);
+            let outputs = ZeroSequenceInjection::<MinMax>::modulate(TwoPhaseReferenceFrame {
+                alpha: value.alpha * 2.0 * FRAC_1_SQRT_3,
+                beta: value.beta * 2.0 * FRAC_1_SQRT_3,
+            });
+
+            for (output, expected) in outputs.into_iter().zip(expected) {
+                assert!((output - expected).abs() < 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    fn third_harmonic_extends_linear_range() {
+        assert!((peak::<Sinusoidal>(1.0) - 1.0).abs() < 0.0001);
+        assert!((peak::<ThirdHarmonicInjection>(1.0) - SQRT_3 / 2.0).abs() < 0.0001);
+        assert!((peak::<ThirdHarmonicInjection>(2.0 * FRAC_1_SQRT_3) - 1.0).abs() < 0.0001);
+        assert!((peak::<ThirdHarmonicInjection<4>>(1.12) - 0.998).abs() < 0.001);
+        assert!((peak::<ZeroSequenceInjection<MinMax>>(2.0 * FRAC_1_SQRT_3) - 1.0).abs() < 0.0001);
+    }
+
+    #[test]
+    fn zero_sequence_keeps_line_to_line_voltages() {
+        struct Offset;
+
+        impl ZeroSequence for Offset {
+            fn zero_sequence(_: &[f32; 3]) -> f32 {
+                0.25
+            }
+        }
+
+        for step in 0..360 {
+            let value = polar(0.7, step as f32 * TAU / 360.0);
+            let expected = line_to_line(Sinusoidal::modulate(value));
+
+            for outputs in [
+                ZeroSequenceInjection::<Offset>::modulate(value),
+                ThirdHarmonicInjection::<6>::modulate(value),
+                ThirdHarmonicInjection::<4>::modulate(value),
+            ] {
+                for (output, expected) in line_to_line(outputs).into_iter().zip(expected) {
+                    assert!((output - expected).abs() < 0.0001);
+                }
+            }
+        }
+
+        let outputs = ZeroSequenceInjection::<Offset>::modulate(polar(0.0, 0.0));
+        assert_eq!(outputs, [0.25; 3]);
+    }
+
+    #[test]
+    fn sectors() {
+        for step in 0..360 {
+            let angle = (step as f32 + 0.5) * TAU / 360.0;
+            let expected = (step / 60 + 1) as u8;
+            assert_eq!(sector(&polar(0.5, angle)), expected);
+        }
+    }
+
+    #[test]
+    fn blanket_modulator_matches_modulation() {
+        let value = polar(0.9, 2.0);
+        let output = SpaceVector.update(value);
+
+        assert_eq!(
+            output.as_compare_value(1000),
+            SpaceVector::as_compare_value(value, 1000)
+        );
+        assert_eq!(output.sector, 2);
+        assert!(!output.saturated);
+        assert!((output.modulation_index - 0.9 * 2.0 * FRAC_1_SQRT_3).abs() < 0.0001);
+
+        let output = Sinusoidal.update(polar(1.1, 0.0));
+        assert!(output.saturated);
+        assert_eq!(output.duty[0], 1.0);
+        assert!((output.modulation_index - 1.1).abs() < 0.0001);
+    }
+
+    #[test]
+    fn space_vector_modulator() {
+        let mut plain = SpaceVectorModulator::new();
+        let value = polar(0.9, 0.3);
+        let expected = SpaceVector.update(value);
+        assert_eq!(plain.update(value), expected);
+
+        // Clamping distorts the vector, while overmodulation keeps its angle
+        let mut limited =
+            SpaceVectorModulator::new().with_overmodulation(Overmodulation::MinimumPhaseError);
+        let output = limited.update(polar(1.5, 0.3));
+        assert!(output.saturated);
+        let line_to_line = [
+            output.duty[0] - output.duty[1],
+            output.duty[1] - output.duty[2],
+        ];
+        let angle = libm::atan2f(
+            (line_to_line[0] + 2.0 * line_to_line[1]) * FRAC_1_SQRT_3,
+            line_to_line[0],
+        ) - core::f32::consts::FRAC_PI_6;
+        assert!((angle - 0.3).abs() < 0.0001, "{angle}");
+    }
+
+    #[test]
+    fn minimum_pulse() {
+        let mut modulator = SpaceVectorModulator::new().with_minimum_pulse(0.05);
+        let mut rounded = 0;
+
+        for step in 0..360 {
+            let value = polar(0.99, step as f32 * TAU / 360.0);
+            let output = modulator.update(value);
+            for duty in output.duty {
+                assert!(duty == 0.0 || duty == 1.0 || (0.05..=0.95).contains(&duty));
+            }
+
+            // Rounding a duty cycle saturates the output
+            let unrounded = SpaceVectorModulator::new().update(value);
+            assert_eq!(output.saturated, output.duty != unrounded.duty, "{step}");
+            assert!(!unrounded.saturated);
+            rounded += output.saturated as usize;
+        }
+        assert!(rounded > 0);
+    }
+
+    #[test]
+    fn voltage_normalisation() {
+        let voltage = polar(10.0, 0.7);
+
+        // The same voltage gives the same line-to-line voltages for each method
+        for bus_voltage in [24.0, 30.0] {
+            let methods = [
+                SpaceVector::modulate_voltage(voltage, bus_voltage),
+                Sinusoidal::modulate_voltage(voltage, bus_voltage),
+                Dpwm1::modulate_voltage(voltage, bus_voltage),
+            ];
+            let phases = crate::park_clarke::inverse_clarke(voltage);
+            let expected = line_to_line([phases.a, phases.b, phases.c]);
+
+            for outputs in methods {
+                let volts = outputs.map(|output| output * bus_voltage / 2.0);
+                for (expected, actual) in expected.iter().zip(line_to_line(volts)) {
+                    assert!((expected - actual).abs() < 0.001, "{expected} {actual}");
+                }
+            }
+        }
+
+        assert_eq!(
+            SpaceVector::voltage_as_compare_value(voltage, 24.0, 1000),
+            SpaceVector::as_compare_value(polar(10.0 / (24.0 * FRAC_1_SQRT_3), 0.7), 1000)
+        );
+        assert_eq!(
+            SpaceVectorModulator::new().update_voltage(voltage, 24.0),
+            SpaceVector.update_voltage(voltage, 24.0)
+        );
+        assert_eq!(Sinusoidal::modulate_voltage(voltage, 0.0), [0.0; 3]);
+    }
+}
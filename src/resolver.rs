@@ -0,0 +1,331 @@
+//! Software resolver-to-digital conversion.
+//!
+//! The sine and cosine windings are expected to be sampled synchronously with
+//! the peak of the excitation signal, which demodulates them into the sine
+//! and cosine of the resolver angle scaled by the excitation amplitude. A
+//! type-II tracking loop then converts these into an angle and speed.
+
+use crate::pll::PhaseLockedLoop;
+
+/// Correction for imperfections in the resolver windings and sampling.
+///
+/// The raw samples are modelled as `sin = (sin(θ + phase) / sin_gain) +
+/// sin_offset` and `cos = (cos(θ) / cos_gain) + cos_offset`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ResolverCalibration {
+    /// Offset of the sine samples
+    pub sin_offset: f32,
+    /// Offset of the cosine samples
+    pub cos_offset: f32,
+    /// Gain applied to the sine samples after removing the offset
+    pub sin_gain: f32,
+    /// Gain applied to the cosine samples after removing the offset
+    pub cos_gain: f32,
+    /// Phase error of the sine winding relative to the cosine winding, in
+    /// radians
+    pub phase: f32,
+}
+
+impl ResolverCalibration {
+    /// Calibration for an ideal resolver, with no correction applied.
+    pub const IDEAL: Self = Self {
+        sin_offset: 0.0,
+        cos_offset: 0.0,
+        sin_gain: 1.0,
+        cos_gain: 1.0,
+        phase: 0.0,
+    };
+}
+
+/// Error detected by the resolver-to-digital converter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResolverError {
+    /// The amplitude of the corrected signals is below the minimum, which
+    /// indicates a disconnected winding or excitation.
+    LossOfSignal(f32),
+    /// The amplitude of the corrected signals is above the maximum, which
+    /// indicates a shorted winding or saturated input.
+    Overrange(f32),
+}
+
+/// A resolver-to-digital converter.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Resolver {
+    calibration: ResolverCalibration,
+    sin_phase: f32,
+    cos_phase: f32,
+    pole_pairs: u8,
+    min_amplitude: f32,
+    max_amplitude: f32,
+    amplitude: f32,
+    pll: PhaseLockedLoop,
+}
+
+impl Resolver {
+    /// Create a new converter with a tracking loop of the given bandwidth in
+    /// radians per second.
+    ///
+    /// `pole_pairs` is the number of electrical cycles of the motor per cycle
+    /// of the resolver.
+    pub fn new(calibration: ResolverCalibration, pole_pairs: u8, bandwidth: f32) -> Self {
+        let (sin_phase, cos_phase) = libm::sincosf(calibration.phase);
+
+        Self {
+            calibration,
+            sin_phase,
+            cos_phase,
+            pole_pairs,
+            min_amplitude: 0.0,
+            max_amplitude: f32::INFINITY,
+            amplitude: 0.0,
+            pll: PhaseLockedLoop::from_bandwidth(bandwidth),
+        }
+    }
+
+    /// Report an error when the amplitude of the corrected signals falls
+    /// outside the given range.
+    pub const fn with_amplitude_limits(mut self, min: f32, max: f32) -> Self {
+        self.min_amplitude = min;
+        self.max_amplitude = max;
+        self
+    }
+
+    /// Update the converter with a new pair of raw samples.
+    ///
+    /// If the amplitude is outside the limits the tracking loop keeps
+    /// extrapolating at the last known speed.
+    pub fn update(&mut self, sin: f32, cos: f32, dt: f32) -> Result<(), ResolverError> {
+        let calibration = &self.calibration;
+        let sin = (sin - calibration.sin_offset) * calibration.sin_gain;
+        let cos = (cos - calibration.cos_offset) * calibration.cos_gain;
+
+        // Remove the phase error using sin(θ + φ) = sin(θ) cos(φ) + cos(θ) sin(φ)
+        let sin = (sin - cos * self.sin_phase) / self.cos_phase;
+
+        self.amplitude = libm::hypotf(sin, cos);
+        let result = if self.amplitude < self.min_amplitude {
+            Err(ResolverError::LossOfSignal(self.amplitude))
+        } else if self.amplitude > self.max_amplitude {
+            Err(ResolverError::Overrange(self.amplitude))
+        } else {
+            Ok(())
+        };
+
+        match result {
+            Ok(()) if self.amplitude > 0.0 => {
+                self.pll
+                    .update_sin_cos(sin / self.amplitude, cos / self.amplitude, dt);
+            }
+            _ => self.pll.update_sin_cos(0.0, 0.0, dt),
+        }
+
+        result
+    }
+
+    /// Amplitude of the corrected signals from the last update.
+    pub fn amplitude(&self) -> f32 {
+        self.amplitude
+    }
+
+    /// Resolver angle in radians, between 0 and 2π.
+    pub fn resolver_angle(&self) -> f32 {
+        self.pll.angle()
+    }
+
+    /// Electrical angle in radians, between 0 and 2π.
+    pub fn angle(&self) -> f32 {
+        crate::wrap_angle(self.pll.angle() * self.pole_pairs as f32)
+    }
+
+    /// Resolver speed in radians per second.
+    pub fn resolver_speed(&self) -> f32 {
+        self.pll.speed()
+    }
+
+    /// Electrical speed in radians per second.
+    pub fn speed(&self) -> f32 {
+        self.pll.speed() * self.pole_pairs as f32
+    }
+}
+
+/// Estimates a [`ResolverCalibration`] from raw samples.
+///
+/// The resolver must be rotated at a constant speed while samples are
+/// collected, so that the angles sampled are evenly distributed. The
+/// estimate is most accurate after a whole number of revolutions.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ResolverCalibrator {
+    count: u32,
+    sin_sum: f32,
+    cos_sum: f32,
+    sin_squared_sum: f32,
+    cos_squared_sum: f32,
+    product_sum: f32,
+}
+
+impl ResolverCalibrator {
+    /// Create a new calibrator with no samples.
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            sin_sum: 0.0,
+            cos_sum: 0.0,
+            sin_squared_sum: 0.0,
+            cos_squared_sum: 0.0,
+            product_sum: 0.0,
+        }
+    }
+
+    /// Record a pair of raw samples.
+    pub fn update(&mut self, sin: f32, cos: f32) {
+        self.count += 1;
+        self.sin_sum += sin;
+        self.cos_sum += cos;
+        self.sin_squared_sum += sin * sin;
+        self.cos_squared_sum += cos * cos;
+        self.product_sum += sin * cos;
+    }
+
+    /// Calibration that normalises the amplitude of the corrected signals to
+    /// 1, or `None` if not enough samples have been recorded.
+    pub fn calibration(&self) -> Option<ResolverCalibration> {
+        if self.count < 2 {
+            return None;
+        }
+
+        let count = self.count as f32;
+        let sin_offset = self.sin_sum / count;
+        let cos_offset = self.cos_sum / count;
+
+        // The variance of a sinusoid with amplitude A is A² / 2
+        let sin_variance = self.sin_squared_sum / count - sin_offset * sin_offset;
+        let cos_variance = self.cos_squared_sum / count - cos_offset * cos_offset;
+        let sin_amplitude = libm::sqrtf(2.0 * sin_variance);
+        let cos_amplitude = libm::sqrtf(2.0 * cos_variance);
+        if sin_amplitude <= 0.0 || cos_amplitude <= 0.0 {
+            return None;
+        }
+
+        // The covariance of sin(θ + φ) and cos(θ) is sin(φ) / 2
+        let covariance = self.product_sum / count - sin_offset * cos_offset;
+        let phase =
+            libm::asinf((2.0 * covariance / (sin_amplitude * cos_amplitude)).clamp(-1.0, 1.0));
+
+        Some(ResolverCalibration {
+            sin_offset,
+            cos_offset,
+            sin_gain: 1.0 / sin_amplitude,
+            cos_gain: 1.0 / cos_amplitude,
+            phase,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.0001;
+
+    /// Raw samples of an imperfect resolver at the given angle.
+    fn samples(angle: f32) -> (f32, f32) {
+        let sin = 0.8 * libm::sinf(angle + 0.05) + 0.1;
+        let cos = 0.9 * libm::cosf(angle) - 0.05;
+        (sin, cos)
+    }
+
+    fn calibration() -> ResolverCalibration {
+        ResolverCalibration {
+            sin_offset: 0.1,
+            cos_offset: -0.05,
+            sin_gain: 1.0 / 0.8,
+            cos_gain: 1.0 / 0.9,
+            phase: 0.05,
+        }
+    }
+
+    #[test]
+    fn learns_calibration() {
+        let mut calibrator = ResolverCalibrator::new();
+        assert_eq!(calibrator.calibration(), None);
+
+        for step in 0..10_000 {
+            let (sin, cos) = samples(step as f32 * core::f32::consts::TAU / 1000.0);
+            calibrator.update(sin, cos);
+        }
+
+        let learned = calibrator.calibration().unwrap();
+        let expected = calibration();
+        assert!((learned.sin_offset - expected.sin_offset).abs() < 0.001);
+        assert!((learned.cos_offset - expected.cos_offset).abs() < 0.001);
+        assert!((learned.sin_gain - expected.sin_gain).abs() < 0.001);
+        assert!((learned.cos_gain - expected.cos_gain).abs() < 0.001);
+        assert!((learned.phase - expected.phase).abs() < 0.001);
+    }
+
+    /// Track a resolver spinning at a constant speed, returning the worst
+    /// angle error once settled.
+    fn track(calibration: ResolverCalibration, speed: f32) -> (Resolver, f32) {
+        let mut resolver = Resolver::new(calibration, 4, 300.0);
+        let mut worst_error: f32 = 0.0;
+
+        for step in 1..=10_000 {
+            let angle = crate::wrap_angle(speed * step as f32 * DT);
+            let (sin, cos) = samples(angle);
+            resolver.update(sin, cos, DT).unwrap();
+
+            if step > 5000 {
+                let error = crate::wrap_angle_difference(resolver.resolver_angle() - angle);
+                worst_error = worst_error.max(error.abs());
+            }
+        }
+
+        (resolver, worst_error)
+    }
+
+    #[test]
+    fn tracks_calibrated_signals() {
+        let (resolver, worst_error) = track(calibration(), 200.0);
+        assert!(worst_error < 0.001, "{worst_error}");
+
+        assert!((resolver.resolver_speed() - 200.0).abs() < 0.5);
+        assert!((resolver.speed() - 800.0).abs() < 2.0);
+        let electrical = resolver.angle() - 4.0 * resolver.resolver_angle();
+        assert!(crate::wrap_angle_difference(electrical).abs() < 0.001);
+    }
+
+    #[test]
+    fn tracks_backwards() {
+        let (resolver, worst_error) = track(calibration(), -200.0);
+        assert!(worst_error < 0.001, "{worst_error}");
+        assert!((resolver.resolver_speed() + 200.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn uncalibrated_signals_cause_error() {
+        let (_, worst_error) = track(ResolverCalibration::IDEAL, 200.0);
+        assert!(worst_error > 0.05, "{worst_error}");
+    }
+
+    #[test]
+    fn loss_of_signal() {
+        let mut resolver = Resolver::new(calibration(), 1, 300.0).with_amplitude_limits(0.5, 1.5);
+
+        let (sin, cos) = samples(1.0);
+        assert_eq!(resolver.update(sin, cos, DT), Ok(()));
+        assert!((resolver.amplitude() - 1.0).abs() < 0.001);
+
+        assert!(matches!(
+            resolver.update(0.1, -0.05, DT),
+            Err(ResolverError::LossOfSignal(_))
+        ));
+        assert!(matches!(
+            resolver.update(2.0, 2.0, DT),
+            Err(ResolverError::Overrange(_))
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+//! A simple surface permanent magnet motor model used to test the observers.
+
+use crate::park_clarke::{inverse_park, RotatingReferenceFrame, TwoPhaseReferenceFrame};
+
+/// Number of integration steps per call to [`Motor::step`].
+const SUBSTEPS: usize = 10;
+
+/// A surface permanent magnet motor spinning at a fixed electrical speed.
+pub struct Motor {
+    pub resistance: f32,
+    pub inductance: f32,
+    pub flux_linkage: f32,
+    /// Electrical angle in radians
+    pub angle: f32,
+    /// Electrical speed in radians per second
+    pub speed: f32,
+    pub current: TwoPhaseReferenceFrame,
+}
+
+impl Motor {
+    pub fn new(resistance: f32, inductance: f32, flux_linkage: f32, speed: f32) -> Self {
+        Self {
+            resistance,
+            inductance,
+            flux_linkage,
+            angle: 0.0,
+            speed,
+            current: TwoPhaseReferenceFrame {
+                alpha: 0.0,
+                beta: 0.0,
+            },
+        }
+    }
+
+    /// Steady-state voltage required to drive the given quadrature axis
+    /// current with no direct axis current.
+    pub fn feedforward(&self, i_q: f32) -> TwoPhaseReferenceFrame {
+        let (sin_angle, cos_angle) = libm::sincosf(self.angle);
+        let voltage = RotatingReferenceFrame {
+            d: -self.speed * self.inductance * i_q,
+            q: self.resistance * i_q + self.speed * self.flux_linkage,
+        };
+
+        inverse_park(cos_angle, sin_angle, voltage)
+    }
+
+    /// Apply the given voltage for `dt` seconds.
+    pub fn step(&mut self, voltage: &TwoPhaseReferenceFrame, dt: f32) {
+        let dt = dt / SUBSTEPS as f32;
+
+        for _ in 0..SUBSTEPS {
+            let (sin_angle, cos_angle) = libm::sincosf(self.angle);
+            let emf = self.speed * self.flux_linkage;
+
+            self.current.alpha +=
+                (voltage.alpha - self.resistance * self.current.alpha + emf * sin_angle) * dt
+                    / self.inductance;
+            self.current.beta +=
+                (voltage.beta - self.resistance * self.current.beta - emf * cos_angle) * dt
+                    / self.inductance;
+
+            self.angle = crate::wrap_angle(self.angle + self.speed * dt);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Phase current reconstruction from a single shunt in the DC link.
+//!
+//! With centre-aligned PWM, the phases switch on in order of decreasing duty
+//! cycle. While only the phase with the highest duty is on, the DC link
+//! current is the current of that phase, and while only the phase with the
+//! lowest duty is off, it is the negated current of that phase. Sampling the
+//! DC link current once in each of these windows gives two phase currents,
+//! and the third follows from them summing to zero.
+//!
+//! The windows are short when the active vectors are short, such as for small
+//! voltages or near the edges of the sectors. [`SingleShunt`] shifts the
+//! rising and falling edges of the phases together, which keeps each duty
+//! cycle the same while guaranteeing a minimum window. This requires a timer
+//! that can set the rising and falling edges of each phase independently.
+
+use crate::{park_clarke::ThreePhaseBalancedReferenceFrame, pwm::ModulatorOutput};
+
+/// Phases ordered by decreasing duty cycle for each space-vector sector.
+const PHASE_ORDER: [[usize; 3]; 6] = [
+    [0, 1, 2],
+    [1, 0, 2],
+    [1, 2, 0],
+    [2, 1, 0],
+    [2, 0, 1],
+    [0, 2, 1],
+];
+
+/// Allowed error in the timing of the sampling windows, as a fraction of the
+/// PWM period.
+const ROUNDING_TOLERANCE: f32 = 1e-6;
+
+/// Timing of the sampling windows for single-shunt current measurement.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SingleShunt {
+    settling_time: f32,
+    sampling_time: f32,
+}
+
+/// The PWM edges and sampling instants for one PWM period, as calculated by
+/// [`SingleShunt::update`].
+///
+/// All times are fractions of the PWM period, from 0 to 1.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SingleShuntPwm {
+    /// Time at which each phase switches on
+    pub rising: [f32; 3],
+    /// Time at which each phase switches off
+    pub falling: [f32; 3],
+    /// Times at which to start sampling the DC link current
+    pub samples: [f32; 2],
+    /// Whether the sampling windows are long enough to measure the currents
+    pub measurable: bool,
+    order: [usize; 3],
+}
+
+impl SingleShunt {
+    /// Create a new single shunt timing, given the time for the DC link
+    /// current to settle after an edge and the time taken to sample it, as
+    /// fractions of the PWM period.
+    ///
+    /// The settling time should include the dead time.
+    pub const fn new(settling_time: f32, sampling_time: f32) -> Self {
+        Self {
+            settling_time,
+            sampling_time,
+        }
+    }
+
+    /// Calculate the PWM edges and sampling instants for the duty cycles and
+    /// sector produced by a space-vector [`Modulator`](crate::pwm::Modulator).
+    ///
+    /// Edges are only shifted as far as needed to fit the minimum window, and
+    /// never beyond the start or end of the PWM period.
+    pub fn update(&self, output: &ModulatorOutput) -> SingleShuntPwm {
+        let window = self.settling_time + self.sampling_time;
+        let order = PHASE_ORDER[(output.sector.clamp(1, 6) - 1) as usize];
+        let [highest, middle, lowest] = order;
+
+        let mut rising = output.duty.map(|duty| (1.0 - duty) / 2.0);
+        let mut falling = output.duty.map(|duty| (1.0 + duty) / 2.0);
+
+        // Open the first window by moving the highest phase earlier, then the
+        // middle phase later
+        let deficit = window - (rising[middle] - rising[highest]);
+        if deficit > 0.0 {
+            let earlier = deficit.min(rising[highest]);
+            let later = (deficit - earlier).min(1.0 - falling[middle]);
+            rising[highest] -= earlier;
+            falling[highest] -= earlier;
+            rising[middle] += later;
+            falling[middle] += later;
+        }
+
+        // Open the second window by moving the lowest phase later
+        let deficit = window - (rising[lowest] - rising[middle]);
+        if deficit > 0.0 {
+            let later = deficit.min(1.0 - falling[lowest]);
+            rising[lowest] += later;
+            falling[lowest] += later;
+        }
+
+        let samples = [
+            rising[highest] + self.settling_time,
+            rising[middle] + self.settling_time,
+        ];
+
+        // Each sample must see the expected phases on and off throughout,
+        // allowing for rounding of windows shifted to exactly the minimum
+        let end = |start: f32| start + self.sampling_time - ROUNDING_TOLERANCE;
+        let on = |phase: usize, start: f32| rising[phase] <= start && end(start) <= falling[phase];
+        let off = |phase: usize, start: f32| end(start) <= rising[phase] || falling[phase] <= start;
+        let measurable = on(highest, samples[0])
+            && off(middle, samples[0])
+            && off(lowest, samples[0])
+            && on(highest, samples[1])
+            && on(middle, samples[1])
+            && off(lowest, samples[1]);
+
+        SingleShuntPwm {
+            rising,
+            falling,
+            samples,
+            measurable,
+            order,
+        }
+    }
+}
+
+impl SingleShuntPwm {
+    /// Reconstruct the phase currents from the DC link current sampled at
+    /// each of the [`samples`](Self::samples) instants.
+    ///
+    /// Returns `None` if the sampling windows were too short, in which case
+    /// the previous measurement or an estimate should be used instead.
+    pub fn reconstruct(&self, samples: [f32; 2]) -> Option<ThreePhaseBalancedReferenceFrame> {
+        if !self.measurable {
+            return None;
+        }
+
+        let [highest, middle, lowest] = self.order;
+        let mut currents = [0.0; 3];
+        currents[highest] = samples[0];
+        currents[lowest] = -samples[1];
+        currents[middle] = samples[1] - samples[0];
+
+        Some(ThreePhaseBalancedReferenceFrame {
+            a: currents[0],
+            b: currents[1],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        park_clarke::TwoPhaseReferenceFrame,
+        pwm::{Modulator, SpaceVectorModulator},
+    };
+
+    const SETTLING_TIME: f32 = 0.03;
+    const SAMPLING_TIME: f32 = 0.02;
+
+    fn modulate(magnitude: f32, angle: f32) -> ModulatorOutput {
+        let (sin_angle, cos_angle) = libm::sincosf(angle);
+        SpaceVectorModulator::new().update(TwoPhaseReferenceFrame {
+            alpha: magnitude * cos_angle,
+            beta: magnitude * sin_angle,
+        })
+    }
+
+    /// DC link current over a sampling window, or `None` if any phase
+    /// switches during it.
+    fn dc_link_current(pwm: &SingleShuntPwm, currents: [f32; 3], start: f32) -> Option<f32> {
+        let end = start + SAMPLING_TIME - ROUNDING_TOLERANCE;
+        let mut current = 0.0;
+        for (phase, phase_current) in currents.iter().enumerate() {
+            let (rising, falling) = (pwm.rising[phase], pwm.falling[phase]);
+            if rising <= start && end <= falling {
+                current += phase_current;
+            } else if !(end <= rising || falling <= start) {
+                return None;
+            }
+        }
+        Some(current)
+    }
+
+    #[test]
+    fn reconstructs_currents() {
+        let shunt = SingleShunt::new(SETTLING_TIME, SAMPLING_TIME);
+        let currents = [1.5, -2.0, 0.5];
+
+        for magnitude in [0.0, 0.05, 0.3, 0.7, 1.0] {
+            for step in 0..72 {
+                let output = modulate(magnitude, step as f32 * 0.0873);
+                let pwm = shunt.update(&output);
+                assert!(pwm.measurable, "{magnitude} {step}");
+
+                // Shifting the edges keeps the duty cycles
+                for phase in 0..3 {
+                    let duty = pwm.falling[phase] - pwm.rising[phase];
+                    assert!((duty - output.duty[phase]).abs() < 1e-6);
+                    assert!(pwm.rising[phase] >= 0.0 && pwm.falling[phase] <= 1.0);
+                }
+
+                let samples = pwm
+                    .samples
+                    .map(|start| dc_link_current(&pwm, currents, start).unwrap());
+                let reconstructed = pwm.reconstruct(samples).unwrap();
+                assert!((reconstructed.a - currents[0]).abs() < 1e-6);
+                assert!((reconstructed.b - currents[1]).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn unshifted_with_long_windows() {
+        let shunt = SingleShunt::new(SETTLING_TIME, SAMPLING_TIME);
+        let output = modulate(0.8, 0.5);
+        let pwm = shunt.update(&output);
+
+        assert_eq!(pwm.rising, output.duty.map(|duty| (1.0 - duty) / 2.0));
+        assert_eq!(pwm.falling, output.duty.map(|duty| (1.0 + duty) / 2.0));
+    }
+
+    #[test]
+    fn unmeasurable_at_vertex() {
+        let shunt = SingleShunt::new(SETTLING_TIME, SAMPLING_TIME);
+
+        // Only phase a is on, so the current of b and c cannot be separated
+        let pwm = shunt.update(&modulate(2.0 / 3.0_f32.sqrt(), 0.0));
+        assert!(!pwm.measurable);
+        assert!(pwm.reconstruct([1.0, 1.0]).is_none());
+    }
+}
@@ -0,0 +1,194 @@
+//! Sliding-mode observer for sensorless estimation of the rotor angle.
+//!
+//! The observer runs a model of the stator current in the stationary
+//! reference frame, driven by a switching term that forces the estimated
+//! current onto the measured current. The low-pass filtered switching term is
+//! an estimate of the back-EMF, which is tracked by a phase-locked loop to
+//! obtain the electrical angle and speed.
+//!
+//! The back-EMF vanishes at standstill, so the estimate is only usable above
+//! a minimum speed.
+
+use crate::{park_clarke::TwoPhaseReferenceFrame, pll::PhaseLockedLoop};
+
+/// A sliding-mode back-EMF observer.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SlidingModeObserver {
+    resistance: f32,
+    inductance: f32,
+    sliding_gain: f32,
+    boundary_layer: f32,
+    filter_cutoff: f32,
+    current: TwoPhaseReferenceFrame,
+    back_emf: TwoPhaseReferenceFrame,
+    pll: PhaseLockedLoop,
+}
+
+impl SlidingModeObserver {
+    /// Create a new observer for a motor with the given phase resistance in
+    /// ohms and phase inductance in henries.
+    ///
+    /// The sliding gain, in volts, must be larger than the largest expected
+    /// back-EMF. The back-EMF estimate is low-pass filtered with the given
+    /// cutoff frequency, and tracked by a phase-locked loop with the given
+    /// bandwidth, both in radians per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `resistance` is not positive.
+    pub const fn new(
+        resistance: f32,
+        inductance: f32,
+        sliding_gain: f32,
+        filter_cutoff: f32,
+        pll_bandwidth: f32,
+    ) -> Self {
+        assert!(resistance > 0.0, "resistance must be positive");
+
+        Self {
+            resistance,
+            inductance,
+            sliding_gain,
+            boundary_layer: 0.0,
+            filter_cutoff,
+            current: TwoPhaseReferenceFrame {
+                alpha: 0.0,
+                beta: 0.0,
+            },
+            back_emf: TwoPhaseReferenceFrame {
+                alpha: 0.0,
+                beta: 0.0,
+            },
+            pll: PhaseLockedLoop::from_bandwidth(pll_bandwidth),
+        }
+    }
+
+    /// Replace the switching function with a saturation function that is
+    /// linear for current errors smaller than the given width in amps,
+    /// reducing chattering.
+    pub const fn with_boundary_layer(mut self, width: f32) -> Self {
+        self.boundary_layer = width;
+        self
+    }
+
+    /// Update the observer with the applied voltage and measured current in
+    /// the stationary reference frame.
+    pub fn update(
+        &mut self,
+        voltage: &TwoPhaseReferenceFrame,
+        current: &TwoPhaseReferenceFrame,
+        dt: f32,
+    ) {
+        // Sliding-mode switching term
+        let switching = |error: f32| {
+            if self.boundary_layer > 0.0 {
+                self.sliding_gain * (error / self.boundary_layer).clamp(-1.0, 1.0)
+            } else {
+                self.sliding_gain * error.signum()
+            }
+        };
+        let z_alpha = switching(self.current.alpha - current.alpha);
+        let z_beta = switching(self.current.beta - current.beta);
+
+        // Exact discretisation of L di/dt = v - R i - z
+        let decay = libm::expf(-self.resistance * dt / self.inductance);
+        let input_gain = (1.0 - decay) / self.resistance;
+        self.current.alpha = decay * self.current.alpha + input_gain * (voltage.alpha - z_alpha);
+        self.current.beta = decay * self.current.beta + input_gain * (voltage.beta - z_beta);
+
+        // The low-frequency content of the switching term is the back-EMF
+        let alpha = 1.0 - libm::expf(-self.filter_cutoff * dt);
+        self.back_emf.alpha += alpha * (z_alpha - self.back_emf.alpha);
+        self.back_emf.beta += alpha * (z_beta - self.back_emf.beta);
+
+        // The back-EMF leads the rotor flux by 90°, and reverses with speed
+        let magnitude = libm::hypotf(self.back_emf.alpha, self.back_emf.beta);
+        if magnitude > 0.0 {
+            let direction = if self.pll.speed() < 0.0 { -1.0 } else { 1.0 };
+            let sin_angle = -direction * self.back_emf.alpha / magnitude;
+            let cos_angle = direction * self.back_emf.beta / magnitude;
+            self.pll.update_sin_cos(sin_angle, cos_angle, dt);
+        }
+    }
+
+    /// Estimated electrical angle in radians, between 0 and 2π.
+    ///
+    /// This includes compensation for the phase lag of the back-EMF filter.
+    pub fn angle(&self) -> f32 {
+        let lag = libm::atanf(self.pll.speed() / self.filter_cutoff);
+        crate::wrap_angle(self.pll.angle() + lag)
+    }
+
+    /// Estimated electrical speed in radians per second.
+    pub fn speed(&self) -> f32 {
+        self.pll.speed()
+    }
+
+    /// Estimated back-EMF in volts, in the stationary reference frame.
+    pub fn back_emf(&self) -> &TwoPhaseReferenceFrame {
+        &self.back_emf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Motor;
+
+    const DT: f32 = 0.00005;
+
+    /// Spin a simulated motor at the given electrical speed, returning the
+    /// observer after one second.
+    fn run(speed: f32) -> (Motor, SlidingModeObserver) {
+        let mut motor = Motor::new(0.5, 0.0005, 0.01, speed);
+        let mut observer =
+            SlidingModeObserver::new(0.5, 0.0005, 20.0, 2000.0, 200.0).with_boundary_layer(0.5);
+
+        for _ in 0..20_000 {
+            let voltage = motor.feedforward(2.0);
+            observer.update(&voltage, &motor.current, DT);
+            motor.step(&voltage, DT);
+        }
+
+        (motor, observer)
+    }
+
+    #[track_caller]
+    fn assert_converges(speed: f32) {
+        let (motor, observer) = run(speed);
+
+        let angle_error = crate::wrap_angle_difference(observer.angle() - motor.angle);
+        assert!(angle_error.abs() < 0.1, "angle error: {angle_error}");
+        assert!(
+            (observer.speed() - speed).abs() < 0.02 * speed.abs(),
+            "speed: {}",
+            observer.speed()
+        );
+    }
+
+    #[test]
+    fn converges_forwards() {
+        assert_converges(500.0);
+        assert_converges(1500.0);
+    }
+
+    #[test]
+    fn converges_backwards() {
+        assert_converges(-800.0);
+    }
+
+    #[test]
+    fn back_emf_estimate() {
+        let (motor, observer) = run(1000.0);
+
+        let magnitude = libm::hypotf(observer.back_emf().alpha, observer.back_emf().beta);
+        let expected = motor.speed * motor.flux_linkage;
+        assert!((magnitude - expected).abs() < 0.1 * expected, "{magnitude}");
+    }
+
+    #[test]
+    fn angle_wraps() {
+        let angle = run(1000.0).1.angle();
+        assert!((0.0..core::f32::consts::TAU).contains(&angle));
+    }
+}
@@ -0,0 +1,283 @@
+//! Limiting of the voltage requested by the current controllers.
+//!
+//! The voltage requested by the direct and quadrature axis controllers often
+//! exceeds what the inverter can produce, such as during fast transients or
+//! at high speed. [`VoltageLimiter`] limits the voltage to the largest circle
+//! the modulation produces without distortion or to the voltage hexagon of
+//! [`SpaceVector`](crate::pwm::SpaceVector), giving either axis priority or
+//! scaling both. The amount removed from each axis should be fed back to the
+//! controllers with
+//! [`PIController::track_saturation`](crate::pid::PIController::track_saturation).
+
+use crate::{
+    angle::ElectricalAngle,
+    overmodulation::EDGE_NORMALS,
+    park_clarke::{RotatingReferenceFrame, TwoPhaseReferenceFrame},
+};
+
+/// The region the voltage is limited to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LimitShape {
+    /// The largest circle which can be produced without distortion, with a
+    /// radius of the voltage scale times `Vdc`, such as `Vdc/√3` for
+    /// space-vector modulation
+    Circle,
+    /// The voltage hexagon of space-vector modulation, which reaches `2Vdc/3`
+    /// at its vertices but produces low-order harmonics outside the inscribed
+    /// circle. Other modulation methods distort outside their circle.
+    Hexagon,
+}
+
+/// How the voltage is reduced when it is outside the limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AxisPriority {
+    /// Keep as much of the direct axis voltage as possible, as needed for field
+    /// weakening
+    D,
+    /// Keep as much of the quadrature axis voltage as possible, maximising
+    /// torque
+    Q,
+    /// Scale both axes equally, keeping the angle of the voltage
+    Proportional,
+}
+
+/// The result of limiting a voltage with a [`VoltageLimiter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LimitedVoltage {
+    /// The limited voltage in the rotating reference frame
+    pub voltage: RotatingReferenceFrame,
+    /// The amount removed from each axis, which is the requested voltage minus
+    /// the limited voltage
+    pub excess: RotatingReferenceFrame,
+}
+
+impl LimitedVoltage {
+    /// Whether the voltage was reduced.
+    pub fn saturated(&self) -> bool {
+        self.excess != RotatingReferenceFrame::default()
+    }
+}
+
+/// A limiter for voltages in the rotating reference frame.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VoltageLimiter {
+    shape: LimitShape,
+    priority: AxisPriority,
+    maximum_modulation: f32,
+}
+
+impl VoltageLimiter {
+    /// Create a new limiter with the given shape and priority.
+    pub const fn new(shape: LimitShape, priority: AxisPriority) -> Self {
+        Self {
+            shape,
+            priority,
+            maximum_modulation: 1.0,
+        }
+    }
+
+    /// Scale the limit to the given fraction, from 0 to 1, for example to
+    /// leave time to sample the currents.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `maximum_modulation` is not between 0 and 1.
+    pub const fn with_maximum_modulation(mut self, maximum_modulation: f32) -> Self {
+        assert!(
+            maximum_modulation >= 0.0 && maximum_modulation <= 1.0,
+            "maximum modulation must be between 0 and 1"
+        );
+
+        self.maximum_modulation = maximum_modulation;
+        self
+    }
+
+    /// Limit a voltage in volts, given the electrical angle, the DC bus
+    /// voltage and the voltage scale of the modulation, such as
+    /// [`Modulation::VOLTAGE_SCALE`](crate::pwm::Modulation::VOLTAGE_SCALE).
+    ///
+    /// The angle is only used by [`LimitShape::Hexagon`].
+    pub fn limit(
+        &self,
+        voltage: RotatingReferenceFrame,
+        angle: &ElectricalAngle,
+        bus_voltage: f32,
+        voltage_scale: f32,
+    ) -> LimitedVoltage {
+        self.limit_with_sin_cos(
+            voltage,
+            angle.cos(),
+            angle.sin(),
+            bus_voltage,
+            voltage_scale,
+        )
+    }
+
+    /// Limit a voltage as [`limit`](Self::limit) does, given the cosine and
+    /// sine of the electrical angle.
+    pub(crate) fn limit_with_sin_cos(
+        &self,
+        voltage: RotatingReferenceFrame,
+        cos_angle: f32,
+        sin_angle: f32,
+        bus_voltage: f32,
+        voltage_scale: f32,
+    ) -> LimitedVoltage {
+        let radius = self.maximum_modulation * voltage_scale * bus_voltage;
+        let d_axis = TwoPhaseReferenceFrame {
+            alpha: cos_angle,
+            beta: sin_angle,
+        };
+        let q_axis = TwoPhaseReferenceFrame {
+            alpha: -sin_angle,
+            beta: cos_angle,
+        };
+
+        // Furthest distance from `start` in the direction of `axis` times the
+        // sign of `value`, limited to the magnitude of `value`
+        let extend = |start: TwoPhaseReferenceFrame, axis: TwoPhaseReferenceFrame, value: f32| {
+            let direction = if value < 0.0 { -axis } else { axis };
+            let distance = self.distance(start, direction, radius);
+            value.clamp(-distance, distance)
+        };
+
+        let limited = match self.priority {
+            AxisPriority::D => {
+                let d = extend(TwoPhaseReferenceFrame::default(), d_axis, voltage.d);
+                let q = extend(d_axis * d, q_axis, voltage.q);
+                RotatingReferenceFrame { d, q }
+            }
+            AxisPriority::Q => {
+                let q = extend(TwoPhaseReferenceFrame::default(), q_axis, voltage.q);
+                let d = extend(q_axis * q, d_axis, voltage.d);
+                RotatingReferenceFrame { d, q }
+            }
+            AxisPriority::Proportional => {
+                let direction = (d_axis * voltage.d + q_axis * voltage.q).normalise();
+                let distance = self.distance(TwoPhaseReferenceFrame::default(), direction, radius);
+                voltage.limit_magnitude(distance)
+            }
+        };
+
+        LimitedVoltage {
+            voltage: limited,
+            excess: voltage - limited,
+        }
+    }
+
+    /// Distance from `start`, which must be within the limit, to its edge in
+    /// the given unit direction.
+    fn distance(
+        &self,
+        start: TwoPhaseReferenceFrame,
+        direction: TwoPhaseReferenceFrame,
+        radius: f32,
+    ) -> f32 {
+        match self.shape {
+            LimitShape::Circle => {
+                let along = start.dot(direction);
+                let discriminant = along * along - start.dot(start) + radius * radius;
+                (libm::sqrtf(discriminant.max(0.0)) - along).max(0.0)
+            }
+            LimitShape::Hexagon => EDGE_NORMALS
+                .iter()
+                .map(|&(alpha, beta)| TwoPhaseReferenceFrame { alpha, beta })
+                .filter(|normal| direction.dot(*normal) > 0.0)
+                .map(|normal| (radius - start.dot(normal)) / direction.dot(normal))
+                .fold(f32::INFINITY, f32::min)
+                .max(0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{park_clarke::inverse_park_with_angle, pwm::space_vector, FRAC_1_SQRT_3, SQRT_3};
+
+    const BUS_VOLTAGE: f32 = 24.0;
+    const RADIUS: f32 = BUS_VOLTAGE * FRAC_1_SQRT_3;
+
+    fn limit(shape: LimitShape, priority: AxisPriority, d: f32, q: f32) -> LimitedVoltage {
+        VoltageLimiter::new(shape, priority).limit(
+            RotatingReferenceFrame { d, q },
+            &ElectricalAngle::new(0.4),
+            BUS_VOLTAGE,
+            FRAC_1_SQRT_3,
+        )
+    }
+
+    /// Largest phase output of space-vector modulation for a voltage.
+    fn peak(voltage: RotatingReferenceFrame) -> f32 {
+        let value = inverse_park_with_angle(&ElectricalAngle::new(0.4), voltage) * (1.0 / RADIUS);
+        space_vector(value)
+            .iter()
+            .fold(0.0_f32, |peak, output| peak.max(output.abs()))
+    }
+
+    #[test]
+    fn within_limit_unchanged() {
+        for shape in [LimitShape::Circle, LimitShape::Hexagon] {
+            for priority in [AxisPriority::D, AxisPriority::Q, AxisPriority::Proportional] {
+                let limited = limit(shape, priority, -3.0, 8.0);
+                assert_eq!(limited.voltage, RotatingReferenceFrame { d: -3.0, q: 8.0 });
+                assert!(!limited.saturated());
+            }
+        }
+    }
+
+    #[test]
+    fn circle_priority() {
+        let limited = limit(LimitShape::Circle, AxisPriority::D, -10.0, 20.0);
+        assert_eq!(limited.voltage.d, -10.0);
+        assert!((limited.voltage.magnitude() - RADIUS).abs() < 0.001);
+
+        let limited = limit(LimitShape::Circle, AxisPriority::Q, -10.0, 20.0);
+        assert!((limited.voltage.q - RADIUS).abs() < 0.001);
+        assert!(limited.voltage.d.abs() < 0.01);
+
+        let limited = limit(LimitShape::Circle, AxisPriority::Proportional, -10.0, 20.0);
+        assert!((limited.voltage.magnitude() - RADIUS).abs() < 0.001);
+        assert!((limited.voltage.q / limited.voltage.d + 2.0).abs() < 0.001);
+
+        assert!(limited.saturated());
+        assert_eq!(
+            limited.voltage + limited.excess,
+            RotatingReferenceFrame { d: -10.0, q: 20.0 }
+        );
+    }
+
+    #[test]
+    fn hexagon_reaches_edge() {
+        for priority in [AxisPriority::D, AxisPriority::Q, AxisPriority::Proportional] {
+            let limited = limit(LimitShape::Hexagon, priority, -10.0, 20.0);
+            assert!((peak(limited.voltage) - 1.0).abs() < 0.001, "{priority:?}");
+
+            // The hexagon allows more than the circle
+            let circle = limit(LimitShape::Circle, priority, -10.0, 20.0);
+            assert!(limited.voltage.magnitude() >= circle.voltage.magnitude());
+            assert!(limited.voltage.magnitude() <= RADIUS * 2.0 / SQRT_3 + 0.001);
+        }
+
+        let limited = limit(LimitShape::Hexagon, AxisPriority::D, -10.0, 20.0);
+        assert_eq!(limited.voltage.d, -10.0);
+    }
+
+    #[test]
+    fn maximum_modulation() {
+        let limited = VoltageLimiter::new(LimitShape::Circle, AxisPriority::Q)
+            .with_maximum_modulation(0.9)
+            .limit(
+                RotatingReferenceFrame { d: 0.0, q: 20.0 },
+                &ElectricalAngle::ZERO,
+                BUS_VOLTAGE,
+                FRAC_1_SQRT_3,
+            );
+
+        assert!((limited.voltage.q - 0.9 * RADIUS).abs() < 0.001);
+    }
+}